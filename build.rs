@@ -0,0 +1,234 @@
+//! Provide the target and build configuration for compile-time diagnostics and the ELF note,
+//! and embed a fingerprint of the build CPU for `-C target-cpu=native` builds.
+
+use std::env;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-env-changed=CARGO_HOME");
+    println!("cargo::rustc-check-cfg=cfg(ensure_simd_native)");
+    println!(
+        "cargo::rustc-env=ENSURE_SIMD_TARGET={}",
+        env::var("TARGET").unwrap()
+    );
+    println!(
+        "cargo::rustc-env=ENSURE_SIMD_BUILD_TARGET_FEATURES={}",
+        env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default()
+    );
+    println!(
+        "cargo::rustc-env=ENSURE_SIMD_RUSTC_VERSION={}",
+        rustc_version()
+    );
+
+    let rustflags = env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+    let rustflags: Vec<&str> = rustflags.split('\x1f').filter(|f| !f.is_empty()).collect();
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    std::fs::write(
+        out_dir.join("diagnostics.txt"),
+        diagnostics(&rustflags, &out_dir),
+    )
+    .unwrap();
+
+    let native = rustflags
+        .iter()
+        .any(|flag| flag.trim_start_matches("-C") == "target-cpu=native");
+    // `native` refers to the build machine, which is only the target machine when not cross-compiling.
+    if !native || env::var("HOST") != env::var("TARGET") {
+        return;
+    }
+
+    let (vendor, model) = cpu_model();
+    println!("cargo::rustc-cfg=ensure_simd_native");
+    println!("cargo::rustc-env=ENSURE_SIMD_BUILD_CPU_VENDOR={vendor}");
+    println!("cargo::rustc-env=ENSURE_SIMD_BUILD_CPU_MODEL={model}");
+}
+
+/// The output of `rustc --version`, e.g. `rustc 1.95.0 (59807616e 2026-04-14)`.
+fn rustc_version() -> String {
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    std::process::Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The vendor and model of the build CPU, read from `/proc/cpuinfo`.
+///
+/// On aarch64, these are the `CPU implementer` and `CPU part` identifiers.
+fn cpu_model() -> (String, String) {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let field = |names: &[&str]| {
+        cpuinfo
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| names.contains(&key.trim()))
+            .map(|(_, value)| value.trim().to_string())
+            .unwrap_or_default()
+    };
+    (
+        field(&["vendor_id", "CPU implementer"]),
+        field(&["model name", "CPU part"]),
+    )
+}
+
+/// The maximum length of the diagnostics, which must fit in the compile-time error.
+const MAX_DIAGNOSTICS: usize = 4096;
+
+/// A description of the build configuration, appended to the compile-time error.
+///
+/// Cargo does not pass `RUSTFLAGS` itself to build scripts, only the final `CARGO_ENCODED_RUSTFLAGS`.
+/// To find out where the flags came from, this looks for `rustflags` in the `.cargo/config.toml`
+/// files that apply to the project being built, and checks whether they reached `rustc`.
+fn diagnostics(rustflags: &[&str], out_dir: &Path) -> String {
+    let var = |name| env::var(name).unwrap_or_default();
+    let features = var("CARGO_CFG_TARGET_FEATURE").replace(',', ", ");
+    let flags = if rustflags.is_empty() {
+        "(none)".to_string()
+    } else {
+        rustflags.join(" ")
+    };
+
+    let mut d = String::new();
+    writeln!(d, "Build configuration seen by ensure_simd:").unwrap();
+    writeln!(d, "  target:          {}", var("TARGET")).unwrap();
+    writeln!(d, "  profile:         {}", var("PROFILE")).unwrap();
+    writeln!(d, "  rustflags:       {flags}").unwrap();
+    writeln!(d, "  target features: {features}").unwrap();
+
+    let mut overridden = false;
+    for (path, flags) in config_rustflags(out_dir) {
+        let applied = flags.iter().all(|f| rustflags.contains(&f.as_str()));
+        overridden |= !applied;
+        let status = if applied { "applied" } else { "NOT applied" };
+        writeln!(d, "  {}: rustflags {flags:?} ({status})", path.display()).unwrap();
+    }
+
+    if out_dir
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with("cargo-install"))
+    {
+        writeln!(d, "Note: `cargo install` ignores the .cargo/config.toml of the installed crate. Use the RUSTFLAGS environment variable instead.").unwrap();
+    } else if overridden {
+        writeln!(d, "Note: the RUSTFLAGS environment variable (and CARGO_ENCODED_RUSTFLAGS) takes precedence over `rustflags` in .cargo/config.toml. Unset it, or add the flags you need to it.").unwrap();
+    } else if rustflags.is_empty() {
+        writeln!(d, "Note: no rustflags are set. Set them using the RUSTFLAGS environment variable or `[build] rustflags` in .cargo/config.toml.").unwrap();
+    }
+    if d.len() > MAX_DIAGNOSTICS {
+        let mut end = MAX_DIAGNOSTICS;
+        while !d.is_char_boundary(end) {
+            end -= 1;
+        }
+        d.truncate(end);
+        d.push_str("…\n");
+    }
+    d
+}
+
+/// The `rustflags` in `.cargo/config.toml` files that apply to the current target.
+///
+/// The project directory is guessed from `OUT_DIR`, which is usually `<project>/target/<profile>/build/<crate>/out`.
+/// This is a best-effort textual scan, not a full TOML parser.
+fn config_rustflags(out_dir: &Path) -> Vec<(PathBuf, Vec<String>)> {
+    let target = env::var("TARGET").unwrap_or_default();
+
+    let mut dirs: Vec<PathBuf> = out_dir
+        .ancestors()
+        .skip_while(|dir| !dir.ends_with("target"))
+        .skip(1)
+        .map(|dir| dir.join(".cargo"))
+        .collect();
+    if let Some(home) = env::var_os("CARGO_HOME") {
+        dirs.push(PathBuf::from(home));
+    }
+
+    let mut result = vec![];
+    for dir in dirs {
+        for file in ["config.toml", "config"] {
+            let path = dir.join(file);
+            let Ok(config) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            println!("cargo::rerun-if-changed={}", path.display());
+            let mut applies = false;
+            for line in config.lines().map(str::trim) {
+                if line.starts_with('[') {
+                    let section = line.replace(' ', "");
+                    applies = section == "[build]"
+                        || section == format!("[target.{target}]")
+                        || section
+                            .strip_prefix("[target.'")
+                            .and_then(|s| s.strip_suffix("']"))
+                            .is_some_and(cfg_matches);
+                } else if applies && line.starts_with("rustflags") {
+                    // The quoted strings are the odd-numbered parts.
+                    let flags = line
+                        .split('"')
+                        .skip(1)
+                        .step_by(2)
+                        .map(String::from)
+                        .collect();
+                    result.push((path.clone(), flags));
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Whether a `cfg(...)` expression without spaces, as in `[target.'cfg(...)']`, holds for the target.
+///
+/// Unknown predicates and malformed expressions do not match.
+fn cfg_matches(cfg: &str) -> bool {
+    let Some(expr) = cfg.strip_prefix("cfg(").and_then(|s| s.strip_suffix(')')) else {
+        return false;
+    };
+    eval_cfg(expr).unwrap_or(false)
+}
+
+fn eval_cfg(expr: &str) -> Option<bool> {
+    if let Some((op, args)) = expr.split_once('(') {
+        let args = split_cfg_args(args.strip_suffix(')')?)?;
+        let mut values = args.into_iter().map(eval_cfg);
+        return match op {
+            "all" => values.try_fold(true, |all, v| Some(all && v?)),
+            "any" => values.try_fold(false, |any, v| Some(any || v?)),
+            "not" if values.len() == 1 => values.next()?.map(|v| !v),
+            _ => None,
+        };
+    }
+    Some(match expr.split_once('=') {
+        // E.g. `target_feature="avx2"`, for which Cargo passes a comma-separated list.
+        Some((key, value)) => {
+            let value = value.strip_prefix('"')?.strip_suffix('"')?;
+            env::var(format!("CARGO_CFG_{}", key.to_uppercase()))
+                .is_ok_and(|values| values.split(',').any(|v| v == value))
+        }
+        // E.g. `unix`.
+        None => env::var_os(format!("CARGO_CFG_{}", expr.to_uppercase())).is_some(),
+    })
+}
+
+/// Split the arguments of `all(...)` and friends at the top-level commas.
+fn split_cfg_args(args: &str) -> Option<Vec<&str>> {
+    let mut parts = vec![];
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&args[start..]);
+    Some(parts.into_iter().filter(|p| !p.is_empty()).collect())
+}
@@ -0,0 +1,14 @@
+//! Run the check, as the first statement of `main`.
+//!
+//! Used by `tests/unsupported_cpu.rs`, which runs it on a CPU without the enabled target features.
+
+fn main() {
+    // Link the checks for libraries as well, which run before `main` or are called by hosts.
+    #[cfg(feature = "load-check")]
+    std::hint::black_box(ensure_simd::load_check as fn() -> _);
+    #[cfg(feature = "capi")]
+    // SAFETY: `buf` may be NULL when `len` is 0.
+    std::hint::black_box(unsafe { ensure_simd::capi::ensure_simd_check(std::ptr::null_mut(), 0) });
+    ensure_simd::ensure_simd();
+    println!("All SIMD instructions compiled into this binary are supported.");
+}
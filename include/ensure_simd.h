@@ -0,0 +1,53 @@
+/*
+ * C interface of ensure_simd, enabled by the `capi` feature.
+ * See https://github.com/ragnargrootkoerkamp/ensure_simd.
+ *
+ * This file mirrors src/capi.rs, and is checked against it by tests/capi_header.rs.
+ */
+
+#ifndef ENSURE_SIMD_H
+#define ENSURE_SIMD_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* SIMD levels, ordered from least to most capable within each architecture. */
+enum ensure_simd_level {
+    ENSURE_SIMD_SCALAR = 0,
+    ENSURE_SIMD_X86_64_V1 = 1,
+    ENSURE_SIMD_X86_64_V2 = 2,
+    ENSURE_SIMD_X86_64_V3 = 3,
+    ENSURE_SIMD_X86_64_V4 = 4,
+    ENSURE_SIMD_NEON = 5,
+    ENSURE_SIMD_SVE = 6,
+    ENSURE_SIMD_SVE2 = 7,
+};
+
+/*
+ * Check that the CPU supports all SIMD features the library was compiled with.
+ * Returns 0 on success, and 1 otherwise.
+ * On failure, the error message is written to `buf` as a NUL-terminated string,
+ * truncated to `len - 1` bytes. `buf` may be NULL when `len` is 0.
+ */
+int ensure_simd_check(char *buf, size_t len);
+
+/* The level supported by the current CPU. */
+int ensure_simd_level(void);
+
+/* The level the library was compiled for. */
+int ensure_simd_compiled_level(void);
+
+/* The minimum level required by the `require-*` features. */
+int ensure_simd_required_level(void);
+
+/* The name of a level, e.g. "x86-64-v3", or NULL for unknown levels. */
+const char *ensure_simd_level_name(int level);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ENSURE_SIMD_H */
@@ -0,0 +1,540 @@
+//! Attribute macros for [`ensure_simd`](https://docs.rs/ensure_simd).
+//!
+//! Use these through the `macros` feature of `ensure_simd`, as `#[ensure_simd::main]`.
+//!
+//! This crate intentionally does not depend on `syn` and `quote`, to keep `ensure_simd` free of dependencies.
+
+use proc_macro::{Delimiter, Group, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Run the `ensure_simd` check as the first statement of `main`.
+///
+/// See the documentation of `ensure_simd::main` for the supported options.
+#[proc_macro_attribute]
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match expand_main(attr, item.clone()) {
+        Ok(tokens) => tokens,
+        Err((span, msg)) => {
+            let mut tokens = compile_error(span, &msg);
+            tokens.extend(item);
+            tokens
+        }
+    }
+}
+
+type Error = (Span, String);
+
+fn expand_main(attr: TokenStream, item: TokenStream) -> Result<TokenStream, Error> {
+    let options = parse_options(attr)?;
+
+    let mut level = None;
+    let mut on_failure = None;
+    let mut exit_code = None;
+    for (key, value) in options {
+        match key.to_string().as_str() {
+            "level" => level = Some(parse_level(&value)?),
+            "on_failure" => on_failure = Some(parse_str(&value)?),
+            "exit_code" => {
+                exit_code = Some(
+                    value
+                        .to_string()
+                        .parse::<i32>()
+                        .map_err(|_| (value.span(), "expected an integer exit code".to_string()))?,
+                )
+            }
+            other => {
+                return Err((
+                    key.span(),
+                    format!(
+                        "unknown option `{other}`; expected `level`, `on_failure`, or `exit_code`"
+                    ),
+                ));
+            }
+        }
+    }
+
+    // Without an `on_failure` option, use the policy set by `ensure_simd::set_failure_policy`.
+    let on_failure = on_failure.unwrap_or_else(|| {
+        if exit_code.is_some() {
+            "exit"
+        } else {
+            "current"
+        }
+        .to_string()
+    });
+    // The policy is passed by `'static` reference, so that `main` itself does not copy it, which
+    // may use e.g. AVX instructions before the check ran.
+    let policy = match on_failure.as_str() {
+        "current" => None,
+        "exit" => Some(format!("Exit({})", exit_code.unwrap_or(1))),
+        "panic" => Some("Panic".to_string()),
+        "abort" => Some("Abort".to_string()),
+        "warn" => Some("Warn".to_string()),
+        _ => {
+            return Err((
+                Span::call_site(),
+                format!(
+                    "unknown `on_failure` policy `{on_failure}`; expected `exit`, `panic`, `abort`, `warn`, or `current`"
+                ),
+            ));
+        }
+    };
+    if exit_code.is_some() && on_failure != "exit" {
+        return Err((
+            Span::call_site(),
+            "`exit_code` requires `on_failure = \"exit\"`".to_string(),
+        ));
+    }
+    let stmt: TokenStream = if level.is_none() && policy.is_none() {
+        "::ensure_simd::ensure_simd();".parse().unwrap()
+    } else {
+        let level = match level {
+            Some(level) => {
+                format!("::core::option::Option::Some(::ensure_simd::SimdLevel::{level})")
+            }
+            None => "::core::option::Option::None".to_string(),
+        };
+        let policy = match policy {
+            Some(policy) => {
+                format!("::core::option::Option::Some(&::ensure_simd::FailurePolicy::{policy})")
+            }
+            None => "::core::option::Option::None".to_string(),
+        };
+        format!("::ensure_simd::__private::ensure_simd({level}, {policy});")
+            .parse()
+            .unwrap()
+    };
+
+    // Insert the check at the start of the function body, which is the last token.
+    let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+    if !tokens
+        .iter()
+        .any(|t| matches!(t, TokenTree::Ident(i) if i.to_string() == "fn"))
+    {
+        return Err((
+            Span::call_site(),
+            "`#[ensure_simd::main]` can only be used on functions".to_string(),
+        ));
+    }
+    // Attributes expand outside in, so an attribute that turns an `async fn` into a sync one, such as
+    // `#[tokio::main]`, must come first for the check to run before the async runtime starts.
+    if let Some(TokenTree::Ident(i)) = tokens
+        .iter()
+        .take_while(|t| !matches!(t, TokenTree::Ident(i) if i.to_string() == "fn"))
+        .find(|t| matches!(t, TokenTree::Ident(i) if i.to_string() == "async"))
+    {
+        return Err((
+            i.span(),
+            "`#[ensure_simd::main]` must be placed below `#[tokio::main]` or a similar attribute that runs an `async fn main`".to_string(),
+        ));
+    }
+    let Some(TokenTree::Group(body)) = tokens.last() else {
+        return Err((Span::call_site(), "expected a function body".to_string()));
+    };
+    if body.delimiter() != Delimiter::Brace {
+        return Err((body.span(), "expected a function body".to_string()));
+    }
+    let mut new_body = stmt;
+    new_body.extend(body.stream());
+    let mut new_body = Group::new(Delimiter::Brace, new_body);
+    new_body.set_span(body.span());
+    *tokens.last_mut().unwrap() = TokenTree::Group(new_body);
+    Ok(tokens.into_iter().collect())
+}
+
+/// Compile a function once per SIMD level, and call the best version supported by the CPU.
+///
+/// See the documentation of `ensure_simd::multiversion` for details.
+#[proc_macro_attribute]
+pub fn multiversion(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match expand_multiversion(attr, item.clone()) {
+        Ok(tokens) => tokens,
+        Err((span, msg)) => {
+            let mut tokens = compile_error(span, &msg);
+            tokens.extend(item);
+            tokens
+        }
+    }
+}
+
+/// A level supported by `#[multiversion]`.
+struct Level {
+    /// The `SimdLevel` variant.
+    variant: &'static str,
+    /// The architecture the clone is compiled for.
+    arch: &'static str,
+    /// The cumulative target features, as in `SimdLevel::target_features`.
+    /// This is checked at compile time by the generated code.
+    features: &'static str,
+}
+
+#[rustfmt::skip]
+const LEVELS: &[(&str, Level)] = &[
+    ("v2", Level { variant: "X86_64V2", arch: "x86_64", features: "sse,sse2,fxsr,sse3,ssse3,sse4.1,sse4.2,popcnt,cmpxchg16b" }),
+    ("v3", Level { variant: "X86_64V3", arch: "x86_64", features: "sse,sse2,fxsr,sse3,ssse3,sse4.1,sse4.2,popcnt,cmpxchg16b,avx,avx2,bmi1,bmi2,f16c,fma,lzcnt,movbe,xsave" }),
+    ("v4", Level { variant: "X86_64V4", arch: "x86_64", features: "sse,sse2,fxsr,sse3,ssse3,sse4.1,sse4.2,popcnt,cmpxchg16b,avx,avx2,bmi1,bmi2,f16c,fma,lzcnt,movbe,xsave,avx512f,avx512bw,avx512cd,avx512dq,avx512vl" }),
+    ("neon", Level { variant: "Neon", arch: "aarch64", features: "neon" }),
+    ("sve", Level { variant: "Sve", arch: "aarch64", features: "neon,sve" }),
+    ("sve2", Level { variant: "Sve2", arch: "aarch64", features: "neon,sve,sve2" }),
+];
+
+fn expand_multiversion(attr: TokenStream, item: TokenStream) -> Result<TokenStream, Error> {
+    // The requested levels, in increasing order.
+    let mut levels = vec![];
+    let mut tokens = attr.into_iter();
+    while let Some(token) = tokens.next() {
+        let TokenTree::Ident(name) = &token else {
+            return Err((token.span(), "expected a level".to_string()));
+        };
+        let Some(index) = LEVELS.iter().position(|(n, _)| *n == name.to_string()) else {
+            return Err((
+                name.span(),
+                format!(
+                    "unknown level `{name}`; expected one of `v2`, `v3`, `v4`, `neon`, `sve`, `sve2`"
+                ),
+            ));
+        };
+        levels.push(index);
+        match tokens.next() {
+            None => break,
+            Some(TokenTree::Punct(p)) if p.as_char() == ',' => {}
+            Some(t) => return Err((t.span(), "expected `,`".to_string())),
+        }
+    }
+    if levels.is_empty() {
+        return Err((
+            Span::call_site(),
+            "expected at least one level, e.g. `#[ensure_simd::multiversion(v2, v3, v4)]`"
+                .to_string(),
+        ));
+    }
+    levels.sort();
+    levels.dedup();
+
+    let f = parse_fn(item)?;
+    let args = split_args(f.args.stream())?;
+
+    // The arguments of the dispatching function, and the values passed to the clones.
+    let mut outer_args = TokenStream::new();
+    let mut call_args = vec![];
+    for (i, (pattern, ty)) in args.iter().enumerate() {
+        let ident = match pattern.as_slice() {
+            [TokenTree::Ident(ident)] => Some(ident),
+            [TokenTree::Ident(m), TokenTree::Ident(ident)] if m.to_string() == "mut" => Some(ident),
+            _ => None,
+        };
+        if let Some(ident) = ident
+            && ident.to_string() == "self"
+        {
+            return Err((
+                ident.span(),
+                "`#[ensure_simd::multiversion]` does not support methods".to_string(),
+            ));
+        }
+        let name = match ident {
+            Some(ident) => ident.to_string(),
+            None if pattern.iter().any(|t| t.to_string() == "self") => {
+                return Err((
+                    pattern[0].span(),
+                    "`#[ensure_simd::multiversion]` does not support methods".to_string(),
+                ));
+            }
+            None => format!("__ensure_simd_arg{i}"),
+        };
+        outer_args.extend(format!("{name}: ").parse::<TokenStream>().unwrap());
+        outer_args.extend(ty.clone());
+        outer_args.extend(",".parse::<TokenStream>().unwrap());
+        call_args.push(name);
+    }
+    let call_args = call_args.join(", ");
+
+    let clone = |name: &str, attrs: &str| -> TokenStream {
+        let mut tokens: TokenStream = attrs.parse().unwrap();
+        tokens.extend(f.qualifiers.clone());
+        tokens.extend(format!("fn {name}").parse::<TokenStream>().unwrap());
+        tokens.extend(f.generics.clone());
+        tokens.extend([TokenTree::Group(f.args.clone())]);
+        tokens.extend(f.tail.clone());
+        tokens.extend([TokenTree::Group(f.body.clone())]);
+        tokens
+    };
+
+    let is_unsafe = f
+        .qualifiers
+        .clone()
+        .into_iter()
+        .any(|t| t.to_string() == "unsafe");
+    let mut body = clone("__ensure_simd_baseline", "");
+    let mut dispatch = String::new();
+    for &index in levels.iter().rev() {
+        let (name, level) = &LEVELS[index];
+        let Level {
+            variant,
+            arch,
+            features,
+        } = level;
+        body.extend(clone(
+            &format!("__ensure_simd_{name}"),
+            &format!("#[cfg(target_arch = \"{arch}\")] #[target_feature(enable = \"{features}\")]"),
+        ));
+        dispatch += &format!(
+            "#[cfg(target_arch = \"{arch}\")]
+            const _: () = ::core::assert!(
+                ::ensure_simd::__private::has_target_features(::ensure_simd::SimdLevel::{variant}, \"{features}\"),
+                \"ensure_simd_macros is out of sync with ensure_simd\",
+            );
+            #[cfg(target_arch = \"{arch}\")]
+            if __ensure_simd_level >= ::ensure_simd::SimdLevel::{variant} {{
+                // SAFETY: the CPU supports all target features of this level.
+                return unsafe {{ __ensure_simd_{name}({call_args}) }};
+            }}"
+        );
+    }
+    let baseline = if is_unsafe {
+        format!("unsafe {{ __ensure_simd_baseline({call_args}) }}")
+    } else {
+        format!("__ensure_simd_baseline({call_args})")
+    };
+    body.extend(
+        format!(
+            "static __ENSURE_SIMD_DISPATCH: ::ensure_simd::__private::Dispatch =
+                ::ensure_simd::__private::Dispatch::new();
+            let __ensure_simd_level = __ENSURE_SIMD_DISPATCH.level();
+            {dispatch}
+            {baseline}"
+        )
+        .parse::<TokenStream>()
+        .unwrap(),
+    );
+
+    let mut tokens = f.prefix;
+    tokens.extend(f.qualifiers);
+    tokens.extend([TokenTree::Ident(f.fn_token), TokenTree::Ident(f.name)]);
+    tokens.extend(f.generics);
+    let mut args = Group::new(Delimiter::Parenthesis, outer_args);
+    args.set_span(f.args.span());
+    tokens.extend([TokenTree::Group(args)]);
+    tokens.extend(f.tail);
+    let mut body = Group::new(Delimiter::Brace, body);
+    body.set_span(f.body.span());
+    tokens.extend([TokenTree::Group(body)]);
+    Ok(tokens)
+}
+
+/// A function item, split into its parts.
+struct Fn {
+    /// Attributes and visibility.
+    prefix: TokenStream,
+    /// E.g. `unsafe` or `extern "C"`.
+    qualifiers: TokenStream,
+    fn_token: proc_macro::Ident,
+    name: proc_macro::Ident,
+    /// The generic parameters, including the angle brackets.
+    generics: TokenStream,
+    args: Group,
+    /// The return type and where clause.
+    tail: TokenStream,
+    body: Group,
+}
+
+fn parse_fn(item: TokenStream) -> Result<Fn, Error> {
+    let mut tokens = item.into_iter().peekable();
+    let mut prefix = TokenStream::new();
+    let mut qualifiers = TokenStream::new();
+    let fn_token = loop {
+        match tokens.next() {
+            Some(TokenTree::Ident(i)) if i.to_string() == "fn" => break i,
+            Some(TokenTree::Ident(i)) if ["const", "async"].contains(&i.to_string().as_str()) => {
+                return Err((
+                    i.span(),
+                    format!("`#[ensure_simd::multiversion]` does not support `{i}` functions"),
+                ));
+            }
+            Some(t @ TokenTree::Ident(_)) if t.to_string() != "pub" => qualifiers.extend([t]),
+            // An ABI, as in `extern "C"`.
+            Some(t @ TokenTree::Literal(_)) => qualifiers.extend([t]),
+            Some(t) => prefix.extend([t]),
+            None => {
+                return Err((
+                    Span::call_site(),
+                    "`#[ensure_simd::multiversion]` can only be used on functions".to_string(),
+                ));
+            }
+        }
+    };
+    let Some(TokenTree::Ident(name)) = tokens.next() else {
+        return Err((fn_token.span(), "expected a function name".to_string()));
+    };
+
+    let mut generics = TokenStream::new();
+    if matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+        let mut depth = 0;
+        let mut prev_dash = false;
+        for t in tokens.by_ref() {
+            if let TokenTree::Punct(p) = &t {
+                match p.as_char() {
+                    '<' => depth += 1,
+                    // The `>` of `->` does not close a bracket.
+                    '>' if !prev_dash => depth -= 1,
+                    _ => {}
+                }
+                prev_dash = p.as_char() == '-' && p.spacing() == Spacing::Joint;
+            } else {
+                prev_dash = false;
+            }
+            generics.extend([t]);
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+
+    let Some(TokenTree::Group(args)) = tokens.next() else {
+        return Err((name.span(), "expected function arguments".to_string()));
+    };
+    let mut rest: Vec<TokenTree> = tokens.collect();
+    let Some(TokenTree::Group(body)) = rest.pop() else {
+        return Err((name.span(), "expected a function body".to_string()));
+    };
+    if body.delimiter() != Delimiter::Brace {
+        return Err((body.span(), "expected a function body".to_string()));
+    }
+    Ok(Fn {
+        prefix,
+        qualifiers,
+        fn_token,
+        name,
+        generics,
+        args,
+        tail: rest.into_iter().collect(),
+        body,
+    })
+}
+
+/// Split function arguments into patterns and types.
+fn split_args(args: TokenStream) -> Result<Vec<(Vec<TokenTree>, TokenStream)>, Error> {
+    let mut result = vec![];
+    let mut pattern = vec![];
+    let mut ty = TokenStream::new();
+    let mut in_ty = false;
+    let mut depth = 0;
+    let mut prev: Option<Punct> = None;
+    for t in args {
+        let punct = match &t {
+            TokenTree::Punct(p) => Some(p.clone()),
+            _ => None,
+        };
+        let after_joint = |c: char| {
+            prev.as_ref()
+                .is_some_and(|p| p.as_char() == c && p.spacing() == Spacing::Joint)
+        };
+        match punct.as_ref().map(Punct::as_char) {
+            Some(',') if depth == 0 => {
+                result.push((std::mem::take(&mut pattern), std::mem::take(&mut ty)));
+                in_ty = false;
+            }
+            // A lone `:`, not part of a `::` path.
+            Some(':')
+                if !in_ty
+                    && punct.as_ref().unwrap().spacing() == Spacing::Alone
+                    && !after_joint(':') =>
+            {
+                in_ty = true;
+            }
+            _ => {
+                match punct.as_ref().map(Punct::as_char) {
+                    Some('<') if in_ty => depth += 1,
+                    Some('>') if in_ty && !after_joint('-') => depth -= 1,
+                    _ => {}
+                }
+                if in_ty {
+                    ty.extend([t]);
+                } else if !matches!(&t, TokenTree::Punct(p) if p.as_char() == '#')
+                    && !matches!(&t, TokenTree::Group(g) if g.delimiter() == Delimiter::Bracket && pattern.is_empty())
+                {
+                    // Attributes on arguments are dropped.
+                    pattern.push(t);
+                }
+            }
+        }
+        prev = punct;
+    }
+    if !pattern.is_empty() {
+        if !in_ty {
+            // `self` receivers have no type.
+            return Err((
+                pattern[0].span(),
+                "`#[ensure_simd::multiversion]` does not support methods".to_string(),
+            ));
+        }
+        result.push((pattern, ty));
+    }
+    Ok(result)
+}
+
+/// Parse `key = value, ...` pairs.
+fn parse_options(attr: TokenStream) -> Result<Vec<(TokenTree, TokenTree)>, Error> {
+    let mut options = vec![];
+    let mut tokens = attr.into_iter();
+    while let Some(key) = tokens.next() {
+        if !matches!(key, TokenTree::Ident(_)) {
+            return Err((key.span(), "expected an option name".to_string()));
+        }
+        match tokens.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '=' => {}
+            _ => return Err((key.span(), format!("expected `{key} = ...`"))),
+        }
+        let Some(value) = tokens.next() else {
+            return Err((key.span(), format!("expected a value for `{key}`")));
+        };
+        options.push((key, value));
+        match tokens.next() {
+            None => break,
+            Some(TokenTree::Punct(p)) if p.as_char() == ',' => {}
+            Some(t) => return Err((t.span(), "expected `,`".to_string())),
+        }
+    }
+    Ok(options)
+}
+
+/// Parse a string literal.
+fn parse_str(value: &TokenTree) -> Result<String, Error> {
+    let s = value.to_string();
+    match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(s) => Ok(s.to_string()),
+        None => Err((value.span(), "expected a string literal".to_string())),
+    }
+}
+
+/// Parse a level name into a `SimdLevel` variant name.
+fn parse_level(value: &TokenTree) -> Result<&'static str, Error> {
+    Ok(match parse_str(value)?.as_str() {
+        "v1" | "x86-64" | "x86-64-v1" => "X86_64V1",
+        "v2" | "x86-64-v2" => "X86_64V2",
+        "v3" | "x86-64-v3" => "X86_64V3",
+        "v4" | "x86-64-v4" => "X86_64V4",
+        "neon" => "Neon",
+        "sve" => "Sve",
+        "sve2" => "Sve2",
+        other => {
+            return Err((
+                value.span(),
+                format!(
+                    "unknown level `{other}`; expected one of `v1`, `v2`, `v3`, `v4`, `neon`, `sve`, `sve2`"
+                ),
+            ));
+        }
+    })
+}
+
+/// A `compile_error!` with the given message, pointing at `span`.
+fn compile_error(span: Span, msg: &str) -> TokenStream {
+    format!("::core::compile_error!({msg:?});")
+        .parse::<TokenStream>()
+        .unwrap()
+        .into_iter()
+        .map(|mut t| {
+            t.set_span(span);
+            t
+        })
+        .collect()
+}
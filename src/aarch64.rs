@@ -0,0 +1,114 @@
+//! Classification of A64 instructions by the width of the SIMD registers they compute on.
+
+use crate::vectors::Width;
+
+/// The width of the SIMD registers of an instruction, for SIMD and floating-point instructions
+/// other than loads, stores, and moves.
+pub(crate) fn width(insn: u32) -> Option<Width> {
+    // SVE and SVE2.
+    if insn >> 29 == 0 && (insn >> 25) & 0xF == 0b0010 {
+        return Some(Width::Scalable);
+    }
+    // Loads and stores, and all other non-SIMD instructions.
+    if (insn >> 25) & 0b111 != 0b111 {
+        return None;
+    }
+    // Scalar floating-point instructions, rather than Advanced SIMD.
+    let fp = (insn >> 29) & 3 == 0 && insn & (1 << 21) != 0;
+    let copy = (insn >> 21) & 0x7 == 0 && insn & (1 << 10) != 0;
+    match (insn >> 24) & 0x1F {
+        // Advanced SIMD modified immediate, such as `movi v0.2d, #0`.
+        0b01111 if (insn >> 19) & 0x1F == 0 && insn & (1 << 10) != 0 => None,
+        // Advanced SIMD copy, such as `dup` and `umov`, and its scalar form.
+        0b01110 if copy => None,
+        0b11110 if copy && insn & (1 << 30) != 0 => None,
+        // `fmov` between floating-point and general-purpose registers.
+        0b11110 if fp && (insn >> 10) & 0x3F == 0 && (insn >> 17) & 3 == 3 => None,
+        // `fmov` between floating-point registers, and of an immediate.
+        0b11110 if fp && ((insn >> 10) & 0x7FF == 0b10000 || (insn >> 10) & 0x7 == 0b100) => None,
+        // Advanced SIMD vector instructions, with the `Q` bit selecting `q` or `d` registers.
+        // Lengthening and narrowing instructions, and the cryptographic extensions, always use
+        // `q` registers.
+        0b01110 | 0b01111 if insn >> 31 == 0 && insn & (1 << 30) == 0 && !long_or_narrow(insn) => {
+            Some(Width::Bits64)
+        }
+        0b01110 | 0b01111 => Some(Width::Bits128),
+        // Advanced SIMD scalar pairwise, such as `addp d0, v1.2d`, of two 64-bit elements.
+        0b11110
+            if insn >> 30 == 1
+                && (insn >> 17) & 0x1F == 0b11000
+                && (insn >> 10) & 3 == 0b10
+                && insn & (1 << 22) != 0
+                && (insn & (1 << 29) != 0 || insn & (1 << 23) != 0) =>
+        {
+            Some(Width::Bits128)
+        }
+        // Scalar floating-point and Advanced SIMD scalar instructions.
+        _ => Some(Width::Scalar),
+    }
+}
+
+/// Whether an Advanced SIMD vector instruction converts between `d` and `q` registers, such as
+/// `ushll v0.8h, v1.8b, #0` and `xtn v0.8b, v1.8h`.
+fn long_or_narrow(insn: u32) -> bool {
+    let opcode = (insn >> 11) & 0x1F;
+    if (insn >> 24) & 0x1F == 0b01111 {
+        // Shift by immediate: `shrn`, `sqshrn`, etc., and `sshll` and `ushll`.
+        return insn & (1 << 10) != 0 && (0b10000..=0b10100).contains(&opcode);
+    }
+    match (insn >> 10) & 3 {
+        // Three registers of different types, such as `uaddl` and `addhn`.
+        0b00 => insn & (1 << 21) != 0,
+        // Two-register miscellaneous: `xtn`, `shll`, `sqxtn`, `fcvtn`, and `fcvtl`.
+        0b10 => {
+            (insn >> 17) & 0x1F == 0b10000
+                && matches!(
+                    (insn >> 12) & 0x1F,
+                    0b10010 | 0b10011 | 0b10100 | 0b10110 | 0b10111
+                )
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vector() {
+        // `add v0.4s, v1.4s, v2.4s`, `fmla v0.2d, v1.2d, v2.2d`, and `aese v0.16b, v1.16b`.
+        assert_eq!(width(0x4EA28420), Some(Width::Bits128));
+        assert_eq!(width(0x4E62CC20), Some(Width::Bits128));
+        assert_eq!(width(0x4E284820), Some(Width::Bits128));
+        // `add v0.2s, v1.2s, v2.2s`.
+        assert_eq!(width(0x0EA28420), Some(Width::Bits64));
+        // `ushll v0.8h, v1.8b, #0`, `xtn v0.8b, v1.8h`, and `uaddl v0.8h, v1.8b, v2.8b`.
+        assert_eq!(width(0x2F08A420), Some(Width::Bits128));
+        assert_eq!(width(0x0E212820), Some(Width::Bits128));
+        assert_eq!(width(0x2E220020), Some(Width::Bits128));
+        // `addp d0, v1.2d`.
+        assert_eq!(width(0x5EF1B820), Some(Width::Bits128));
+        // `add z0.s, z1.s, z2.s`.
+        assert_eq!(width(0x04A20020), Some(Width::Scalable));
+    }
+
+    #[test]
+    fn scalar() {
+        // `fadd s0, s1, s2`.
+        assert_eq!(width(0x1E222820), Some(Width::Scalar));
+    }
+
+    #[test]
+    fn moves_and_other() {
+        // `ldr q0, [x0]` and `add x0, x1, x2`.
+        assert_eq!(width(0x3DC00000), None);
+        assert_eq!(width(0x8B020020), None);
+        // `movi v0.2d, #0` and `dup v0.4s, w1`.
+        assert_eq!(width(0x6F00E400), None);
+        assert_eq!(width(0x4E040C20), None);
+        // `fmov s0, w1` and `fmov d0, d1`.
+        assert_eq!(width(0x1E270020), None);
+        assert_eq!(width(0x1E604020), None);
+    }
+}
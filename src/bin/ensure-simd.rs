@@ -0,0 +1,560 @@
+//! `ensure-simd`: report the SIMD capabilities of the current CPU, and the requirements of binaries.
+//!
+//! Install using `cargo install ensure_simd --features cli`, and run `ensure-simd` or `ensure-simd --json`.
+
+use ensure_simd::{
+    BinaryInfo, Compatibility, CpuFingerprint, FeatureSet, FunctionVectors, ScanReport, SimdLevel,
+    VectorReport,
+};
+
+const USAGE: &str = "Usage: ensure-simd [--json]
+       ensure-simd inspect <binary> [--target-cpu <cpu>]... [--json]
+       ensure-simd scan <binary> [--level <level>] [--json]
+       ensure-simd vectors <binary> <function>... [--min-width <bits>] [--json]
+
+Report the SIMD level and features of this CPU, the best `-C target-cpu` value,
+and whether binaries built with the recommended portable settings run here.
+
+`inspect` reads the build metadata that `ensure_simd` embeds in an ELF binary, and reports
+its required SIMD level and whether it runs on this CPU, or on each given `-C target-cpu`
+(e.g. `x86-64-v3` or `znver2`, as known to `rustc`). Exits with code 1 when it does not.
+
+`scan` disassembles an x86-64 ELF binary, and reports the ISA extensions its instructions
+actually use, and in which functions. With `--level`, exits with code 1 when it uses
+instructions beyond that level (e.g. `v3`).
+
+`vectors` disassembles an x86-64 or aarch64 ELF binary, and counts the SIMD instructions of
+each register width in the given functions. A function is a demangled name or its last path
+segments, in which `*` matches anything (e.g. `my_crate::hot_loop` or `dot*`). Exits with code 1
+when a function only uses vectors narrower than `--min-width`, or is not found, e.g. because
+it was inlined.
+
+Options:
+  --target-cpu <cpu>  Check a target CPU instead of this CPU. Can be repeated.
+  --level <level>     The highest level `scan` may find.
+  --min-width <bits>  The vector width `vectors` expects: 64, 128, 256, or 512. Defaults to
+                      256 on x86-64 and 128 on aarch64.
+  --json              Print the report as JSON.
+  --help              Print this help.
+  --version           Print the version.";
+
+/// The subcommand to run.
+enum Command {
+    /// Report the capabilities of the host.
+    Host,
+    Inspect(String),
+    Scan(String),
+    /// Report the vector widths of the functions matching the patterns in a binary.
+    Vectors(String, Vec<String>),
+}
+
+/// A portable build configuration recommended in the readme.
+struct Recommendation {
+    target_cpu: &'static str,
+    /// Whether the recommendation applies to the architecture of this CPU.
+    applies: bool,
+    features: FeatureSet,
+}
+
+/// As reported by `rustc --print cfg --target aarch64-apple-darwin -C target-cpu=apple-a14`.
+#[rustfmt::skip]
+const APPLE_A14: &[&str] = &[
+    "aes", "crc", "dit", "dotprod", "dpb", "dpb2", "fcma", "fhm", "flagm", "fp16",
+    "frintts", "jsconv", "lse", "neon", "paca", "pacg", "rcpc", "rcpc2", "rdm", "sb",
+    "sha2", "sha3", "ssbs",
+];
+
+/// The recommendations of the readme.
+fn recommendations() -> [Recommendation; 2] {
+    [
+        Recommendation {
+            target_cpu: "x86-64-v3",
+            applies: cfg!(target_arch = "x86_64"),
+            features: FeatureSet::from_known_names(SimdLevel::X86_64V3.target_features()),
+        },
+        Recommendation {
+            target_cpu: "apple-a14",
+            applies: cfg!(target_arch = "aarch64"),
+            features: FeatureSet::from_known_names(APPLE_A14),
+        },
+    ]
+}
+
+/// The most specific portable `-C target-cpu` value for this CPU.
+fn best_target_cpu(level: SimdLevel) -> &'static str {
+    match level {
+        SimdLevel::X86_64V1 | SimdLevel::X86_64V2 | SimdLevel::X86_64V3 | SimdLevel::X86_64V4 => {
+            level.name()
+        }
+        _ => "native",
+    }
+}
+
+fn main() {
+    let mut json = false;
+    let mut command = Command::Host;
+    let mut target_cpus = vec![];
+    let mut max_level = None;
+    let mut min_bits = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "inspect" | "scan" | "vectors" if matches!(command, Command::Host) => {
+                let Some(path) = args.next() else {
+                    usage_error(&format!("Missing binary to {arg}."));
+                };
+                command = match arg.as_str() {
+                    "inspect" => Command::Inspect(path),
+                    "scan" => Command::Scan(path),
+                    _ => Command::Vectors(path, vec![]),
+                };
+            }
+            "--target-cpu" if matches!(command, Command::Inspect(_)) => match args.next() {
+                Some(cpu) => target_cpus.push(cpu),
+                None => usage_error("Missing value for `--target-cpu`."),
+            },
+            "--level" if matches!(command, Command::Scan(_)) => {
+                match args.next().map(|level| level.parse::<SimdLevel>()) {
+                    Some(Ok(level)) => max_level = Some(level),
+                    Some(Err(err)) => usage_error(&format!("Invalid value for `--level`: {err}.")),
+                    None => usage_error("Missing value for `--level`."),
+                }
+            }
+            "--min-width" if matches!(command, Command::Vectors(..)) => {
+                match args.next().map(|bits| bits.parse::<u32>()) {
+                    Some(Ok(bits @ (64 | 128 | 256 | 512))) => min_bits = Some(bits),
+                    Some(_) => usage_error(
+                        "Invalid value for `--min-width`: expected 64, 128, 256, or 512.",
+                    ),
+                    None => usage_error("Missing value for `--min-width`."),
+                }
+            }
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                return;
+            }
+            "--version" | "-V" => {
+                println!("ensure-simd {}", env!("CARGO_PKG_VERSION"));
+                return;
+            }
+            _ if !arg.starts_with('-') && matches!(command, Command::Vectors(..)) => {
+                if let Command::Vectors(_, patterns) = &mut command {
+                    patterns.push(arg);
+                }
+            }
+            _ => usage_error(&format!("Unknown argument `{arg}`.")),
+        }
+    }
+
+    match command {
+        Command::Host => {}
+        Command::Inspect(path) => return inspect(&path, &target_cpus, json),
+        Command::Scan(path) => return scan(&path, max_level, json),
+        Command::Vectors(_, patterns) if patterns.is_empty() => {
+            usage_error("Missing functions to report the vectors of.")
+        }
+        Command::Vectors(path, patterns) => return vectors(&path, &patterns, min_bits, json),
+    }
+
+    let host = CpuFingerprint::host();
+    let level = SimdLevel::detected();
+    if json {
+        print_json(&host, level);
+    } else {
+        print_human(&host, level);
+    }
+}
+
+fn print_human(host: &CpuFingerprint, level: SimdLevel) {
+    println!("CPU:        {host}");
+    println!("Arch:       {}", std::env::consts::ARCH);
+    println!("SIMD level: {level}");
+    println!("Features:   {}", host.features);
+    println!(
+        "Best target-cpu: {} (or `native` for binaries built and run on this machine only)",
+        best_target_cpu(level)
+    );
+    println!();
+    println!("Binaries built with the recommended portable settings:");
+    for r in recommendations() {
+        let status = if !r.applies {
+            "not applicable to this architecture".to_string()
+        } else {
+            let missing = r.features.difference(&host.features);
+            if missing.is_empty() {
+                "runs on this CPU".to_string()
+            } else {
+                format!("does NOT run on this CPU, missing: {missing}")
+            }
+        };
+        println!("  -C target-cpu={:<10} {status}", r.target_cpu);
+    }
+}
+
+fn print_json(host: &CpuFingerprint, level: SimdLevel) {
+    let strings = |set: FeatureSet| {
+        set.iter()
+            .map(|f| format!("\"{f}\""))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let recommendations = recommendations()
+        .iter()
+        .map(|r| {
+            let (runs, missing) = if r.applies {
+                let missing = r.features.difference(&host.features);
+                (missing.is_empty().to_string(), strings(missing))
+            } else {
+                ("null".to_string(), String::new())
+            };
+            format!(
+                "    {{\"target_cpu\": \"{}\", \"applies\": {}, \"runs\": {runs}, \"missing\": [{missing}]}}",
+                r.target_cpu, r.applies
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n");
+    println!("{{");
+    println!("  \"arch\": \"{}\",", std::env::consts::ARCH);
+    println!("  \"vendor\": {},", json_string(&host.vendor));
+    println!("  \"model\": {},", json_string(&host.model));
+    println!("  \"level\": \"{level}\",");
+    println!("  \"features\": [{}],", strings(host.features));
+    println!("  \"best_target_cpu\": \"{}\",", best_target_cpu(level));
+    println!("  \"recommendations\": [\n{recommendations}\n  ]");
+    println!("}}");
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{message}\n\n{USAGE}");
+    std::process::exit(2);
+}
+
+/// Inspect the binary at `path`, and exit with code 1 when it does not run on the host,
+/// or on one of the `target_cpus` when given.
+fn inspect(path: &str, target_cpus: &[String], json: bool) {
+    let info = match ensure_simd::inspect(path) {
+        Ok(info) => info,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            std::process::exit(2);
+        }
+    };
+    let checks: Vec<(Option<&str>, Compatibility)> = if target_cpus.is_empty() {
+        vec![(None, info.check_host())]
+    } else {
+        target_cpus
+            .iter()
+            .map(|cpu| match info.check_target_cpu(cpu) {
+                Ok(compatibility) => (Some(cpu.as_str()), compatibility),
+                Err(err) => {
+                    eprintln!("{err}");
+                    std::process::exit(2);
+                }
+            })
+            .collect()
+    };
+    if json {
+        print_inspect_json(path, &info, &checks);
+    } else {
+        print_inspect_human(path, &info, &checks);
+    }
+    if !checks.iter().all(|(_, c)| c.runs()) {
+        std::process::exit(1);
+    }
+}
+
+fn print_inspect_human(path: &str, info: &BinaryInfo, checks: &[(Option<&str>, Compatibility)]) {
+    println!("Binary:     {path}");
+    println!("Target:     {}", info.target);
+    println!("SIMD level: {}", info.level);
+    println!("Scalar:     {}", info.scalar);
+    println!("Rustc:      {}", info.rustc);
+    println!("Features:   {}", info.target_features.join(","));
+    println!();
+    for (cpu, compatibility) in checks {
+        let cpu = match cpu {
+            Some(cpu) => format!("-C target-cpu={cpu}"),
+            None => "this CPU".to_string(),
+        };
+        match compatibility {
+            Compatibility::Runs => println!("Runs on {cpu}."),
+            Compatibility::Missing(missing) => {
+                println!("Does NOT run on {cpu}, missing: {}", missing.join(","))
+            }
+            Compatibility::OtherArch { binary, cpu: arch } => {
+                println!("Does NOT run on {cpu}: built for {binary}, not {arch}.")
+            }
+        }
+    }
+}
+
+fn print_inspect_json(path: &str, info: &BinaryInfo, checks: &[(Option<&str>, Compatibility)]) {
+    let strings = |names: &[String]| {
+        names
+            .iter()
+            .map(|f| json_string(f))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let checks = checks
+        .iter()
+        .map(|(cpu, compatibility)| {
+            let cpu = cpu.map_or("null".to_string(), json_string);
+            let (runs, missing) = match compatibility {
+                Compatibility::Runs => (true, String::new()),
+                Compatibility::Missing(missing) => (false, strings(missing)),
+                Compatibility::OtherArch { .. } => (false, String::new()),
+            };
+            format!("    {{\"target_cpu\": {cpu}, \"runs\": {runs}, \"missing\": [{missing}]}}")
+        })
+        .collect::<Vec<_>>()
+        .join(",\n");
+    println!("{{");
+    println!("  \"binary\": {},", json_string(path));
+    println!("  \"target\": {},", json_string(&info.target));
+    println!("  \"arch\": {},", json_string(info.arch()));
+    println!("  \"level\": \"{}\",", info.level);
+    println!("  \"scalar\": {},", info.scalar);
+    println!("  \"rustc\": {},", json_string(&info.rustc));
+    println!("  \"features\": [{}],", strings(&info.target_features));
+    println!("  \"checks\": [\n{checks}\n  ]");
+    println!("}}");
+}
+
+/// Scan the binary at `path`, and exit with code 1 when it uses instructions beyond `max_level`.
+fn scan(path: &str, max_level: Option<SimdLevel>, json: bool) {
+    let report = match ensure_simd::scan(path) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            std::process::exit(2);
+        }
+    };
+    if json {
+        print_scan_json(path, &report, max_level);
+    } else {
+        print_scan_human(path, &report, max_level);
+    }
+    if max_level.is_some_and(|level| report.beyond(level).next().is_some()) {
+        std::process::exit(1);
+    }
+}
+
+/// The number of functions listed per extension in the human-readable scan report.
+const MAX_FUNCTIONS: usize = 10;
+
+fn print_scan_human(path: &str, report: &ScanReport, max_level: Option<SimdLevel>) {
+    println!("Binary:        {path}");
+    println!("Highest level: {}", report.level());
+    for e in &report.extensions {
+        let level = e.level.map_or("no level", SimdLevel::name);
+        println!();
+        println!(
+            "{} ({level}): {} in {}",
+            e.feature,
+            plural(e.instructions, "instruction"),
+            plural(e.functions.len(), "function")
+        );
+        for f in e.functions.iter().take(MAX_FUNCTIONS) {
+            println!("  {f}");
+        }
+        if e.functions.len() > MAX_FUNCTIONS {
+            println!(
+                "  ... and {} more (use --json for all)",
+                e.functions.len() - MAX_FUNCTIONS
+            );
+        }
+    }
+    if let Some(level) = max_level {
+        let beyond: Vec<&str> = report.beyond(level).map(|e| e.feature).collect();
+        println!();
+        if beyond.is_empty() {
+            println!("No instructions beyond {level}.");
+        } else {
+            println!(
+                "Instructions beyond {level}: {}. Code behind run-time detection may be fine.",
+                beyond.join(", ")
+            );
+        }
+    }
+}
+
+/// E.g. `1 function` or `2 functions`.
+fn plural(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("{n} {noun}")
+    } else {
+        format!("{n} {noun}s")
+    }
+}
+
+fn print_scan_json(path: &str, report: &ScanReport, max_level: Option<SimdLevel>) {
+    let extensions = report
+        .extensions
+        .iter()
+        .map(|e| {
+            let functions = e
+                .functions
+                .iter()
+                .map(|f| json_string(f))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let beyond = max_level.map_or("null".to_string(), |level| e.is_beyond(level).to_string());
+            format!(
+                "    {{\"feature\": \"{}\", \"level\": {}, \"beyond\": {beyond}, \"instructions\": {}, \"functions\": [{functions}]}}",
+                e.feature,
+                e.level.map_or("null".to_string(), |l| format!("\"{l}\"")),
+                e.instructions
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n");
+    println!("{{");
+    println!("  \"binary\": {},", json_string(path));
+    println!("  \"level\": \"{}\",", report.level());
+    println!("  \"extensions\": [\n{extensions}\n  ]");
+    println!("}}");
+}
+
+/// Report the vector widths of the functions matching `patterns` in the binary at `path`, and
+/// exit with code 1 when one is narrower than `min_bits`, or a pattern matches no function.
+fn vectors(path: &str, patterns: &[String], min_bits: Option<u32>, json: bool) {
+    let report = match ensure_simd::vectors(path) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            std::process::exit(2);
+        }
+    };
+    let min_bits = min_bits.unwrap_or_else(|| report.default_min_bits());
+    let mut functions: Vec<&FunctionVectors> = vec![];
+    let mut unmatched: Vec<&str> = vec![];
+    for pattern in patterns {
+        let len = functions.len();
+        functions.extend(report.matching(pattern));
+        if functions.len() == len {
+            unmatched.push(pattern);
+        }
+    }
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    functions.dedup_by(|a, b| a.name == b.name);
+    if json {
+        print_vectors_json(path, &report, &functions, &unmatched, min_bits);
+    } else {
+        print_vectors_human(path, &report, &functions, &unmatched, min_bits);
+    }
+    if !unmatched.is_empty() || functions.iter().any(|f| !f.is_vectorized(min_bits)) {
+        std::process::exit(1);
+    }
+}
+
+/// The column names and counts of the vector report, per register width.
+fn vector_columns(arch: &str, f: &FunctionVectors) -> Vec<(&'static str, usize)> {
+    if arch == "x86_64" {
+        vec![
+            ("scalar", f.scalar),
+            ("mmx", f.bits64),
+            ("xmm", f.bits128),
+            ("ymm", f.bits256),
+            ("zmm", f.bits512),
+        ]
+    } else {
+        vec![
+            ("scalar", f.scalar),
+            ("d", f.bits64),
+            ("q", f.bits128),
+            ("sve", f.scalable),
+        ]
+    }
+}
+
+fn print_vectors_human(
+    path: &str,
+    report: &VectorReport,
+    functions: &[&FunctionVectors],
+    unmatched: &[&str],
+    min_bits: u32,
+) {
+    println!("Binary:    {path}");
+    println!("Arch:      {}", report.arch);
+    println!("Min width: {min_bits} bits");
+    println!();
+    let header = vector_columns(report.arch, &FunctionVectors::default())
+        .iter()
+        .map(|(name, _)| format!("{name:>7}"))
+        .collect::<String>();
+    println!("{:>7}{header}  function", "insns");
+    for f in functions {
+        let counts = vector_columns(report.arch, f)
+            .iter()
+            .map(|(_, count)| format!("{count:>7}"))
+            .collect::<String>();
+        let flag = match f.widest_bits() {
+            _ if f.is_vectorized(min_bits) => String::new(),
+            0 => "  <- scalar only".to_string(),
+            bits => format!("  <- only {bits}-bit vectors"),
+        };
+        println!("{:>7}{counts}  {}{flag}", f.instructions, f.name);
+    }
+    for pattern in unmatched {
+        println!("No function matches `{pattern}`; it may have been inlined.");
+    }
+    println!();
+    println!("Loads, stores, and moves are not counted.");
+}
+
+fn print_vectors_json(
+    path: &str,
+    report: &VectorReport,
+    functions: &[&FunctionVectors],
+    unmatched: &[&str],
+    min_bits: u32,
+) {
+    let functions = functions
+        .iter()
+        .map(|f| {
+            format!(
+                "    {{\"name\": {}, \"instructions\": {}, \"scalar\": {}, \"bits64\": {}, \"bits128\": {}, \"bits256\": {}, \"bits512\": {}, \"scalable\": {}, \"widest_bits\": {}, \"vectorized\": {}}}",
+                json_string(&f.name),
+                f.instructions,
+                f.scalar,
+                f.bits64,
+                f.bits128,
+                f.bits256,
+                f.bits512,
+                f.scalable,
+                f.widest_bits(),
+                f.is_vectorized(min_bits)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n");
+    let unmatched = unmatched
+        .iter()
+        .map(|p| json_string(p))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!("{{");
+    println!("  \"binary\": {},", json_string(path));
+    println!("  \"arch\": \"{}\",", report.arch);
+    println!("  \"min_width\": {min_bits},");
+    println!("  \"functions\": [\n{functions}\n  ],");
+    println!("  \"unmatched\": [{unmatched}]");
+    println!("}}");
+}
+
+/// A JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            c if c.is_control() => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out + "\""
+}
@@ -0,0 +1,98 @@
+//! `extern "C"` functions for C, C++, Go, and other non-Rust hosts that link a `staticlib` or `cdylib`.
+//!
+//! The declarations are in `include/ensure_simd.h`, which must be kept in sync with this file.
+//! `tests/capi_header.rs` compiles and links it against these functions.
+//! Levels are passed as the `int` value of [`SimdLevel`], in the order of [`SimdLevel::ALL`].
+
+use core::ffi::{CStr, c_char, c_int};
+
+use crate::error::UNSUPPORTED;
+use crate::features::compiled_supported;
+use crate::{SimdLevel, check_simd};
+
+const _: () = {
+    let mut i = 0;
+    while i < SimdLevel::ALL.len() {
+        assert!(
+            SimdLevel::ALL[i] as usize == i,
+            "`SimdLevel::ALL` must match the C enum"
+        );
+        i += 1;
+    }
+};
+
+/// Run [`check_simd`]. Returns 0 when all required features are supported, and 1 otherwise.
+///
+/// On failure, the error message is written to `buf` as a NUL-terminated string,
+/// truncated to `len - 1` bytes. `buf` may be NULL when `len` is 0.
+///
+/// # Safety
+/// `buf` must be valid for writing `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ensure_simd_check(buf: *mut c_char, len: usize) -> c_int {
+    // The full check is compiled with the enabled target features as well, so first check those
+    // using only code that also runs on CPUs without them.
+    if !compiled_supported() {
+        // SAFETY: guaranteed by the caller.
+        unsafe { write_message(UNSUPPORTED, buf, len) };
+        return 1;
+    }
+    let Err(err) = check_simd() else {
+        return 0;
+    };
+    // SAFETY: guaranteed by the caller.
+    unsafe { write_message(&err.to_string(), buf, len) };
+    1
+}
+
+/// Write `msg` to `buf` as a NUL-terminated string, truncated to `len - 1` bytes.
+///
+/// # Safety
+/// `buf` must be valid for writing `len` bytes, or `len` must be 0.
+#[inline(never)]
+unsafe fn write_message(msg: &str, buf: *mut c_char, len: usize) {
+    if buf.is_null() || len == 0 {
+        return;
+    }
+    let n = msg.len().min(len - 1);
+    // SAFETY: `n < len`, and the caller guarantees that `buf` is valid for `len` bytes.
+    unsafe {
+        core::ptr::copy_nonoverlapping(msg.as_ptr().cast(), buf, n);
+        *buf.add(n) = 0;
+    }
+}
+
+/// The level supported by the current CPU. See [`SimdLevel::detected`].
+#[unsafe(no_mangle)]
+pub extern "C" fn ensure_simd_level() -> c_int {
+    SimdLevel::detected() as c_int
+}
+
+/// The level the library was compiled for. See [`SimdLevel::compiled`].
+#[unsafe(no_mangle)]
+pub extern "C" fn ensure_simd_compiled_level() -> c_int {
+    SimdLevel::compiled() as c_int
+}
+
+/// The minimum level required by the `require-*` features. See [`SimdLevel::required`].
+#[unsafe(no_mangle)]
+pub extern "C" fn ensure_simd_required_level() -> c_int {
+    SimdLevel::required() as c_int
+}
+
+/// The name of a level, e.g. `x86-64-v3`, as a static NUL-terminated string, or NULL for unknown levels.
+#[unsafe(no_mangle)]
+pub extern "C" fn ensure_simd_level_name(level: c_int) -> *const c_char {
+    let name: &CStr = match SimdLevel::ALL.get(level as usize) {
+        Some(SimdLevel::Scalar) => c"scalar",
+        Some(SimdLevel::X86_64V1) => c"x86-64",
+        Some(SimdLevel::X86_64V2) => c"x86-64-v2",
+        Some(SimdLevel::X86_64V3) => c"x86-64-v3",
+        Some(SimdLevel::X86_64V4) => c"x86-64-v4",
+        Some(SimdLevel::Neon) => c"neon",
+        Some(SimdLevel::Sve) => c"sve",
+        Some(SimdLevel::Sve2) => c"sve2",
+        None => return core::ptr::null(),
+    };
+    name.as_ptr()
+}
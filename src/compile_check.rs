@@ -0,0 +1,213 @@
+//! The compile-time check that the [required](SimdLevel::required) SIMD level is enabled.
+//!
+//! The error message is assembled at compile time, so that it can name the target,
+//! the missing target features, and the `RUSTFLAGS` that enable them.
+//! It ends with the build configuration as seen by the build script.
+
+use crate::features::FEATURES;
+use crate::{FeatureSet, SimdLevel};
+
+const _: () = {
+    // The `cli` feature is only meant for `cargo install ensure_simd --features cli`, which
+    // builds the `ensure-simd` tool for machines that may lack the required level.
+    // `CARGO_PRIMARY_PACKAGE` is only set when building this package itself, so that a dependency
+    // enabling `cli` does not disable the check for the crates using this one.
+    let skip = cfg!(any(doc, debug_assertions, feature = "scalar"))
+        || (cfg!(feature = "cli") && option_env!("CARGO_PRIMARY_PACKAGE").is_some());
+    let enabled = SimdLevel::compiled().meets(SimdLevel::required())
+        && FeatureSet::compiled().is_superset(&SimdLevel::required_features());
+    if !skip && !enabled {
+        panic!("{}", MESSAGE.as_str());
+    }
+};
+
+/// The target triple, e.g. `x86_64-unknown-linux-gnu`.
+const TARGET: &str = env!("ENSURE_SIMD_TARGET");
+
+const MESSAGE: Message = message();
+
+const fn message() -> Message {
+    let mut m = Message::new();
+    let required = SimdLevel::required();
+    if let SimdLevel::Scalar = required {
+        m.push("\nThe tool you are trying to build uses SIMD instructions for performance (AVX2 on x86-64 and NEON on aarch64),\nbut ensure_simd knows no SIMD fast path for target `");
+        m.push(TARGET);
+        m.push("`.\n");
+        if let Some(feature) = arch_simd_feature() {
+            m.push("SIMD on this architecture would need e.g. the `");
+            m.push(feature);
+            m.push("` target feature, but ensure_simd does not check it.\n");
+        }
+        m.push(
+            "To build anyway, activate the `scalar` feature (eg `cargo install -F scalar ...`).\n",
+        );
+    } else {
+        let missing = SimdLevel::required_features().difference(&FeatureSet::compiled());
+        m.push("\nThe tool you are trying to build uses ");
+        m.push(description(required));
+        m.push(" SIMD instructions for performance,\nbut target `");
+        m.push(TARGET);
+        m.push("` does not have the required target features enabled: ");
+        m.push_features(missing, "", ", ");
+        m.push(".\nTo get the expected performance, compile/install using e.g.:\nRUSTFLAGS=\"-C target-cpu=native\" cargo ...\nor, for a portable binary:\nRUSTFLAGS=\"-C ");
+        if cfg!(all(target_arch = "x86_64", feature = "require-v2")) {
+            m.push("target-cpu=");
+            m.push(required.name());
+        } else {
+            m.push("target-feature=");
+            m.push_features(missing, "+", ",");
+        }
+        m.push("\" cargo ...\nAlternatively, silence this error by activating the `scalar` feature (eg `cargo install -F scalar ...`).\n");
+    }
+    m.push("See the readme at https://github.com/ragnargrootkoerkamp/ensure_simd for details.\n\n");
+    m.push(DIAGNOSTICS);
+    m
+}
+
+/// The target features, rustflags, and their origin, as written by the build script.
+const DIAGNOSTICS: &str = include_str!(concat!(env!("OUT_DIR"), "/diagnostics.txt"));
+
+/// The level, together with its most notable feature.
+const fn description(level: SimdLevel) -> &'static str {
+    match level {
+        SimdLevel::Scalar => "scalar",
+        // Only required without a `require-*` feature, which also requires AVX2.
+        SimdLevel::X86_64V1 => "AVX2",
+        SimdLevel::X86_64V2 => "x86-64-v2 (SSE4.2)",
+        SimdLevel::X86_64V3 => "x86-64-v3 (AVX2)",
+        SimdLevel::X86_64V4 => "x86-64-v4 (AVX-512)",
+        SimdLevel::Neon => "NEON",
+        SimdLevel::Sve => "SVE",
+        SimdLevel::Sve2 => "SVE2",
+    }
+}
+
+/// The main SIMD target feature of architectures without a [`SimdLevel`].
+const fn arch_simd_feature() -> Option<&'static str> {
+    if cfg!(any(target_arch = "wasm32", target_arch = "wasm64")) {
+        Some("simd128")
+    } else if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+        Some("v")
+    } else if cfg!(any(target_arch = "powerpc", target_arch = "powerpc64")) {
+        Some("vsx")
+    } else if cfg!(target_arch = "loongarch64") {
+        Some("lasx")
+    } else if cfg!(target_arch = "s390x") {
+        Some("vector")
+    } else if cfg!(target_arch = "arm") {
+        Some("neon")
+    } else {
+        None
+    }
+}
+
+/// A string buffer that can be written at compile time.
+///
+/// Text beyond its capacity is dropped, and the message then ends with an ellipsis.
+pub(crate) struct Message {
+    buf: [u8; CAPACITY + ELLIPSIS.len()],
+    len: usize,
+    truncated: bool,
+}
+
+/// The maximum length of a [`Message`], excluding the ellipsis.
+const CAPACITY: usize = 8192;
+const ELLIPSIS: &[u8] = "…".as_bytes();
+
+impl Message {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; CAPACITY + ELLIPSIS.len()],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Append `s`, or as much of it as fits followed by an ellipsis.
+    pub const fn push(&mut self, s: &str) {
+        if self.truncated {
+            return;
+        }
+        let s = s.as_bytes();
+        let mut end = s.len();
+        if end > CAPACITY - self.len {
+            end = CAPACITY - self.len;
+            // Do not split a multi-byte character.
+            while end > 0 && s[end] & 0xC0 == 0x80 {
+                end -= 1;
+            }
+            self.truncated = true;
+        }
+        self.extend(s, end);
+        if self.truncated {
+            self.extend(ELLIPSIS, ELLIPSIS.len());
+        }
+    }
+
+    const fn extend(&mut self, s: &[u8], end: usize) {
+        let mut i = 0;
+        while i < end {
+            self.buf[self.len] = s[i];
+            self.len += 1;
+            i += 1;
+        }
+    }
+
+    /// Push the names of the features in `set`, each prefixed by `prefix` and separated by `sep`.
+    pub const fn push_features(&mut self, set: FeatureSet, prefix: &str, sep: &str) {
+        let mut first = true;
+        let mut i = 0;
+        while i < FEATURES.len() {
+            if set.bits() & (1 << i) != 0 {
+                if !first {
+                    self.push(sep);
+                }
+                self.push(prefix);
+                self.push(FEATURES[i].name);
+                first = false;
+            }
+            i += 1;
+        }
+    }
+
+    pub const fn as_str(&self) -> &str {
+        match core::str::from_utf8(self.buf.split_at(self.len).0) {
+            Ok(s) => s,
+            Err(_) => panic!("invalid utf-8"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push() {
+        let mut m = Message::new();
+        m.push("foo");
+        m.push("");
+        m.push("bar");
+        assert_eq!(m.as_str(), "foobar");
+    }
+
+    #[test]
+    fn push_truncates() {
+        let mut m = Message::new();
+        m.push("start ");
+        m.push(&"x".repeat(2 * CAPACITY));
+        m.push("end");
+        assert_eq!(m.as_str().len(), CAPACITY + ELLIPSIS.len());
+        assert!(m.as_str().starts_with("start xx"));
+        assert!(m.as_str().ends_with("xx…"));
+    }
+
+    #[test]
+    fn push_truncates_at_char_boundary() {
+        let mut m = Message::new();
+        m.push(&"x".repeat(CAPACITY - 1));
+        m.push("é");
+        assert_eq!(m.as_str().len(), CAPACITY - 1 + ELLIPSIS.len());
+        assert!(m.as_str().ends_with("x…"));
+    }
+}
@@ -0,0 +1,230 @@
+//! Run-time feature detection on x86 using the `cpuid` and `xgetbv` instructions.
+//!
+//! This only uses `core::arch`, and reads all relevant `cpuid` leaves once.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{__cpuid, __cpuid_count};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+#[cfg(feature = "std")]
+use core::hint::black_box;
+
+/// A register in the output of `cpuid`.
+#[derive(Clone, Copy)]
+pub(crate) enum Reg {
+    Eax,
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+/// The register state the OS must save on context switches for a feature to be usable.
+#[derive(Clone, Copy)]
+pub(crate) enum OsSupport {
+    /// No additional OS support needed.
+    None,
+    /// `xsave` must be enabled by the OS.
+    Xsave,
+    /// The OS must save the `xmm` and `ymm` registers.
+    Avx,
+    /// The OS must additionally save the `zmm` and `k` mask registers.
+    Avx512,
+}
+
+/// The location of a feature bit in the output of `cpuid`.
+#[derive(Clone, Copy)]
+pub(crate) struct Bit {
+    pub leaf: u32,
+    pub subleaf: u32,
+    pub reg: Reg,
+    /// The feature bit, as a mask rather than its index, so that checking it needs no shifts.
+    pub mask: u32,
+    pub os: OsSupport,
+}
+
+/// All `(leaf, subleaf)` pairs that are referenced by the feature table.
+const LEAVES: [(u32, u32); 6] = [
+    (0x1, 0),
+    (0x7, 0),
+    (0x7, 1),
+    (0xD, 1),
+    (0x19, 0),
+    (0x8000_0001, 0),
+];
+
+/// XCR0 bits for the SSE and AVX state.
+const XCR0_AVX: u64 = 0b110;
+/// XCR0 bits for the SSE, AVX, and AVX-512 opmask and upper `zmm` state.
+const XCR0_AVX512: u64 = 0b1110_0110;
+
+/// The `cpuid` bits and `xcr0` state required by a set of features, computed at compile time.
+///
+/// Checking these only compares the raw words against the masks, so that it does not run code
+/// that may use the target features being checked, such as variable shifts using `shlx`.
+#[cfg(feature = "std")]
+pub(crate) struct Masks {
+    leaves: [[u32; 4]; LEAVES.len()],
+    xcr0: u64,
+}
+
+#[cfg(feature = "std")]
+impl Masks {
+    pub const EMPTY: Self = Self {
+        leaves: [[0; 4]; LEAVES.len()],
+        xcr0: 0,
+    };
+
+    /// Also require the given feature bit.
+    pub const fn with(mut self, bit: &Bit) -> Self {
+        let mut idx = 0;
+        while LEAVES[idx].0 != bit.leaf || LEAVES[idx].1 != bit.subleaf {
+            idx += 1;
+        }
+        self.leaves[idx][bit.reg as usize] |= bit.mask;
+        let xcr0 = match bit.os {
+            OsSupport::None => return self,
+            OsSupport::Xsave => 0,
+            OsSupport::Avx => XCR0_AVX,
+            OsSupport::Avx512 => XCR0_AVX512,
+        };
+        // `osxsave`.
+        self.leaves[0][Reg::Ecx as usize] |= 1 << 27;
+        self.xcr0 |= xcr0;
+        self
+    }
+
+    /// Whether the current CPU has all required bits.
+    #[inline(always)]
+    pub fn supported(&self) -> bool {
+        let max_leaf = __cpuid(0).eax;
+        let max_extended_leaf = __cpuid(0x8000_0000).eax;
+        self.leaf_supported(0, max_leaf)
+            && self.leaf_supported(1, max_leaf)
+            && self.leaf_supported(2, max_leaf)
+            && self.leaf_supported(3, max_leaf)
+            && self.leaf_supported(4, max_leaf)
+            && self.leaf_supported(5, max_extended_leaf)
+            // Only read after `osxsave` has been checked as part of leaf `0x1`.
+            && (self.xcr0 == 0 || has_bits(xgetbv0(), self.xcr0))
+    }
+
+    #[inline(always)]
+    fn leaf_supported(&self, idx: usize, max: u32) -> bool {
+        let mask = self.leaves[idx];
+        if mask[0] | mask[1] | mask[2] | mask[3] == 0 {
+            return true;
+        }
+        let (leaf, subleaf) = LEAVES[idx];
+        if leaf > max {
+            return false;
+        }
+        let r = __cpuid_count(leaf, subleaf);
+        has_bits(r.eax, mask[0])
+            && has_bits(r.ebx, mask[1])
+            && has_bits(r.ecx, mask[2])
+            && has_bits(r.edx, mask[3])
+    }
+}
+
+/// Whether `word` has all bits of `mask` set.
+///
+/// The comparison goes through `black_box`, so that LLVM can not combine it with others into
+/// vector instructions, or rewrite it using `andn`.
+#[cfg(feature = "std")]
+#[inline(always)]
+fn has_bits<T: Copy + PartialEq + core::ops::BitAnd<Output = T>>(word: T, mask: T) -> bool {
+    black_box(word & mask) == mask
+}
+
+/// The `cpuid` leaves and `xcr0` register of the current CPU.
+pub(crate) struct Snapshot {
+    leaves: [[u32; 4]; LEAVES.len()],
+    xcr0: u64,
+}
+
+impl Snapshot {
+    pub fn read() -> Self {
+        let max_leaf = __cpuid(0).eax;
+        let max_extended_leaf = __cpuid(0x8000_0000).eax;
+
+        let mut leaves = [[0; 4]; LEAVES.len()];
+        for (regs, &(leaf, subleaf)) in leaves.iter_mut().zip(&LEAVES) {
+            let max = if leaf >= 0x8000_0000 {
+                max_extended_leaf
+            } else {
+                max_leaf
+            };
+            if leaf <= max {
+                let r = __cpuid_count(leaf, subleaf);
+                *regs = [r.eax, r.ebx, r.ecx, r.edx];
+            }
+        }
+
+        // `osxsave`: the OS has enabled `xgetbv` to query the saved register state.
+        let osxsave = leaves[0][Reg::Ecx as usize] & (1 << 27) != 0;
+        let xcr0 = if osxsave { xgetbv0() } else { 0 };
+
+        Self { leaves, xcr0 }
+    }
+
+    pub fn has(&self, bit: &Bit) -> bool {
+        let Some(idx) = LEAVES.iter().position(|&l| l == (bit.leaf, bit.subleaf)) else {
+            return false;
+        };
+        if self.leaves[idx][bit.reg as usize] & bit.mask == 0 {
+            return false;
+        }
+        let osxsave = self.leaves[0][Reg::Ecx as usize] & (1 << 27) != 0;
+        match bit.os {
+            OsSupport::None => true,
+            OsSupport::Xsave => osxsave,
+            OsSupport::Avx => self.xcr0 & XCR0_AVX == XCR0_AVX,
+            OsSupport::Avx512 => self.xcr0 & XCR0_AVX512 == XCR0_AVX512,
+        }
+    }
+}
+
+/// Read the `xcr0` extended control register.
+///
+/// This uses inline assembly rather than `_xgetbv`, so that it does not require the `xsave` target feature.
+fn xgetbv0() -> u64 {
+    let (eax, edx): (u32, u32);
+    // SAFETY: only called when `osxsave` is set, meaning that `xgetbv` is available.
+    unsafe {
+        core::arch::asm!(
+            "xgetbv",
+            in("ecx") 0,
+            out("eax") eax,
+            out("edx") edx,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    ((edx as u64) << 32) | eax as u64
+}
+
+/// The vendor string, e.g. `GenuineIntel` or `AuthenticAMD`.
+#[cfg(feature = "std")]
+pub(crate) fn vendor() -> [u8; 12] {
+    let r = __cpuid(0);
+    let mut vendor = [0; 12];
+    for (chunk, reg) in vendor.chunks_exact_mut(4).zip([r.ebx, r.edx, r.ecx]) {
+        chunk.copy_from_slice(&reg.to_le_bytes());
+    }
+    vendor
+}
+
+/// The processor brand string, e.g. `AMD Ryzen 9 7950X 16-Core Processor`, padded with zeros or spaces.
+#[cfg(feature = "std")]
+pub(crate) fn brand() -> [u8; 48] {
+    let mut brand = [0; 48];
+    if __cpuid(0x8000_0000).eax < 0x8000_0004 {
+        return brand;
+    }
+    for (i, chunk) in brand.chunks_exact_mut(16).enumerate() {
+        let r = __cpuid(0x8000_0002 + i as u32);
+        for (c, reg) in chunk.chunks_exact_mut(4).zip([r.eax, r.ebx, r.ecx, r.edx]) {
+            c.copy_from_slice(&reg.to_le_bytes());
+        }
+    }
+    brand
+}
@@ -0,0 +1,23 @@
+//! Run [`ensure_simd`](crate::ensure_simd) before `main`, via an ELF `.init_array` constructor.
+//!
+//! This protects code that runs before `main`, such as static initializers and global allocators.
+//!
+//! Note that `rustc` only links crates that are referenced, so binaries that do not otherwise use
+//! `ensure_simd` need a `use ensure_simd as _;`.
+
+/// Registered with priority 101, the first priority available to user code,
+/// so that it runs before unprioritized constructors of other libraries.
+#[used]
+#[unsafe(link_section = ".init_array.00101")]
+static ENSURE_SIMD: extern "C" fn() = ensure_simd;
+
+extern "C" fn ensure_simd() {
+    #[cfg(all(
+        feature = "sigill",
+        target_os = "linux",
+        any(target_env = "gnu", target_env = "musl"),
+        any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    crate::install_sigill_handler();
+    crate::ensure_simd();
+}
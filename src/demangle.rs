@@ -0,0 +1,522 @@
+//! Demangling of Rust symbol names, for reporting the functions found in binaries.
+//!
+//! Supports the legacy and the v0 mangling schemes. Hashes, disambiguators, and lifetimes are
+//! left out. Names that can not be demangled are returned unchanged.
+
+/// The maximum recursion depth and output length, to bound the work on malformed names.
+const MAX_DEPTH: u32 = 100;
+const MAX_LEN: usize = 4096;
+
+/// Demangle a symbol name.
+pub(crate) fn demangle(name: &str) -> String {
+    let demangled = if let Some(rest) = name.strip_prefix("_ZN") {
+        legacy(rest)
+    } else if let Some(rest) = name.strip_prefix("_R") {
+        // Drop suffixes added by LLVM, such as `.llvm.1234`.
+        let rest = rest.split('.').next().unwrap_or_default();
+        V0::new(rest).demangle()
+    } else {
+        None
+    };
+    demangled.unwrap_or_else(|| name.to_string())
+}
+
+/// Demangle a legacy name: length-prefixed identifiers ending in `E`, the last being the hash.
+fn legacy(mut rest: &str) -> Option<String> {
+    let mut path = vec![];
+    while !rest.starts_with('E') {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let len: usize = rest[..digits].parse().ok()?;
+        let end = digits.checked_add(len)?;
+        path.push(rest.get(digits..end)?);
+        rest = &rest[end..];
+    }
+    if path.last().is_some_and(|h| {
+        h.len() == 17 && h.starts_with('h') && h[1..].bytes().all(|b| b.is_ascii_hexdigit())
+    }) {
+        path.pop();
+    }
+
+    let mut out = String::new();
+    for (i, ident) in path.iter().enumerate() {
+        if i > 0 {
+            out += "::";
+        }
+        let mut ident = if ident.starts_with("_$") {
+            &ident[1..]
+        } else {
+            ident
+        };
+        while !ident.is_empty() {
+            if let Some(s) = ident.strip_prefix("..") {
+                out += "::";
+                ident = s;
+            } else if let Some((escape, s)) =
+                ident.strip_prefix('$').and_then(|s| s.split_once('$'))
+            {
+                out.push(match escape {
+                    "SP" => '@',
+                    "BP" => '*',
+                    "RF" => '&',
+                    "LT" => '<',
+                    "GT" => '>',
+                    "LP" => '(',
+                    "RP" => ')',
+                    "C" => ',',
+                    _ => escape
+                        .strip_prefix('u')
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .and_then(char::from_u32)?,
+                });
+                ident = s;
+            } else {
+                let c = ident.chars().next()?;
+                out.push(c);
+                ident = &ident[c.len_utf8()..];
+            }
+        }
+    }
+    Some(out)
+}
+
+/// A demangler for the v0 scheme. See <https://doc.rust-lang.org/rustc/symbol-mangling/v0.html>.
+struct V0<'a> {
+    s: &'a [u8],
+    pos: usize,
+    out: String,
+    /// Whether to parse without printing, for impl paths.
+    skip: bool,
+    depth: u32,
+}
+
+impl<'a> V0<'a> {
+    fn new(s: &'a str) -> Self {
+        V0 {
+            s: s.as_bytes(),
+            pos: 0,
+            out: String::new(),
+            skip: false,
+            depth: 0,
+        }
+    }
+
+    fn demangle(mut self) -> Option<String> {
+        // The encoding version.
+        while self.peek()?.is_ascii_digit() {
+            self.pos += 1;
+        }
+        self.path()?;
+        // The instantiating crate is left out.
+        Some(self.out)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        let eaten = self.peek() == Some(b);
+        if eaten {
+            self.pos += 1;
+        }
+        eaten
+    }
+
+    fn print(&mut self, s: &str) -> Option<()> {
+        if !self.skip {
+            self.out += s;
+        }
+        (self.out.len() <= MAX_LEN).then_some(())
+    }
+
+    /// A base-62 number, terminated by `_`.
+    fn base62(&mut self) -> Option<usize> {
+        if self.eat(b'_') {
+            return Some(0);
+        }
+        let mut x: usize = 0;
+        loop {
+            let d = match self.next()? {
+                b @ b'0'..=b'9' => b - b'0',
+                b @ b'a'..=b'z' => b - b'a' + 10,
+                b @ b'A'..=b'Z' => b - b'A' + 36,
+                b'_' => return x.checked_add(1),
+                _ => return None,
+            };
+            x = x.checked_mul(62)?.checked_add(d as usize)?;
+        }
+    }
+
+    /// Skip an optional `{tag}<base62>`, as used for disambiguators and binders.
+    fn skip_tagged(&mut self, tag: u8) -> Option<()> {
+        if self.eat(tag) {
+            self.base62()?;
+        }
+        Some(())
+    }
+
+    fn decimal(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek()?.is_ascii_digit() {
+            self.pos += 1;
+        }
+        core::str::from_utf8(&self.s[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// An identifier, without its disambiguator.
+    fn ident(&mut self) -> Option<&'a str> {
+        self.skip_tagged(b's')?;
+        // Punycode identifiers are printed as is.
+        self.eat(b'u');
+        let len = self.decimal()?;
+        self.eat(b'_');
+        let ident = self.s.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        core::str::from_utf8(ident).ok()
+    }
+
+    /// Parse the target of a backref using `f`, and continue after it.
+    fn backref(&mut self, f: fn(&mut Self) -> Option<()>) -> Option<()> {
+        let start = self.pos - 1;
+        let target = self.base62()?;
+        if target >= start {
+            return None;
+        }
+        let pos = self.pos;
+        self.pos = target;
+        f(self)?;
+        self.pos = pos;
+        Some(())
+    }
+
+    /// Run `f` one level deeper, bounding the recursion.
+    fn nested(&mut self, f: fn(&mut Self) -> Option<()>) -> Option<()> {
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn path(&mut self) -> Option<()> {
+        self.nested(Self::path_inner)
+    }
+
+    fn path_inner(&mut self) -> Option<()> {
+        match self.next()? {
+            b'C' => {
+                let ident = self.ident()?;
+                self.print(ident)
+            }
+            b'N' => {
+                let ns = self.next()?;
+                self.path()?;
+                let ident = self.ident()?;
+                match ns {
+                    b'a'..=b'z' if ident.is_empty() => Some(()),
+                    b'a'..=b'z' => {
+                        self.print("::")?;
+                        self.print(ident)
+                    }
+                    _ => {
+                        let ns = match ns {
+                            b'C' => "closure",
+                            b'S' => "shim",
+                            _ => "",
+                        };
+                        self.print("::{")?;
+                        self.print(ns)?;
+                        if !ident.is_empty() {
+                            self.print(":")?;
+                            self.print(ident)?;
+                        }
+                        self.print("}")
+                    }
+                }
+            }
+            tag @ (b'M' | b'X') => {
+                self.skip_tagged(b's')?;
+                // The impl path is left out.
+                let skip = core::mem::replace(&mut self.skip, true);
+                let impl_path = self.path();
+                self.skip = skip;
+                impl_path?;
+                self.print("<")?;
+                self.ty()?;
+                if tag == b'X' {
+                    self.print(" as ")?;
+                    self.path()?;
+                }
+                self.print(">")
+            }
+            b'Y' => {
+                self.print("<")?;
+                self.ty()?;
+                self.print(" as ")?;
+                self.path()?;
+                self.print(">")
+            }
+            b'I' => {
+                self.path()?;
+                self.print("<")?;
+                self.list(b'E', Self::generic_arg)?;
+                self.print(">")
+            }
+            b'B' => self.backref(Self::path),
+            _ => None,
+        }
+    }
+
+    /// Elements parsed by `f` until `end`, separated by commas.
+    fn list(&mut self, end: u8, f: fn(&mut Self) -> Option<()>) -> Option<usize> {
+        let mut n = 0;
+        while !self.eat(end) {
+            if n > 0 {
+                self.print(", ")?;
+            }
+            f(self)?;
+            n += 1;
+        }
+        Some(n)
+    }
+
+    fn generic_arg(&mut self) -> Option<()> {
+        if self.eat(b'L') {
+            self.base62()?;
+            self.print("'_")
+        } else if self.eat(b'K') {
+            self.konst()
+        } else {
+            self.ty()
+        }
+    }
+
+    fn ty(&mut self) -> Option<()> {
+        self.nested(Self::ty_inner)
+    }
+
+    fn ty_inner(&mut self) -> Option<()> {
+        let basic = match self.peek()? {
+            b'a' => "i8",
+            b'b' => "bool",
+            b'c' => "char",
+            b'd' => "f64",
+            b'e' => "str",
+            b'f' => "f32",
+            b'h' => "u8",
+            b'i' => "isize",
+            b'j' => "usize",
+            b'l' => "i32",
+            b'm' => "u32",
+            b'n' => "i128",
+            b'o' => "u128",
+            b'p' => "_",
+            b's' => "i16",
+            b't' => "u16",
+            b'u' => "()",
+            b'v' => "...",
+            b'x' => "i64",
+            b'y' => "u64",
+            b'z' => "!",
+            b'C' | b'M' | b'X' | b'Y' | b'N' | b'I' => return self.path(),
+            _ => "",
+        };
+        let tag = self.next()?;
+        if !basic.is_empty() {
+            return self.print(basic);
+        }
+        match tag {
+            b'A' | b'S' => {
+                self.print("[")?;
+                self.ty()?;
+                if tag == b'A' {
+                    self.print("; ")?;
+                    self.konst()?;
+                }
+                self.print("]")
+            }
+            b'T' => {
+                self.print("(")?;
+                if self.list(b'E', Self::ty)? == 1 {
+                    self.print(",")?;
+                }
+                self.print(")")
+            }
+            b'R' | b'Q' => {
+                self.skip_tagged(b'L')?;
+                self.print(if tag == b'R' { "&" } else { "&mut " })?;
+                self.ty()
+            }
+            b'P' | b'O' => {
+                self.print(if tag == b'P' { "*const " } else { "*mut " })?;
+                self.ty()
+            }
+            b'F' => {
+                self.skip_tagged(b'G')?;
+                if self.eat(b'U') {
+                    self.print("unsafe ")?;
+                }
+                if self.eat(b'K') {
+                    let abi = if self.eat(b'C') { "C" } else { self.ident()? };
+                    self.print("extern \"")?;
+                    self.print(abi)?;
+                    self.print("\" ")?;
+                }
+                self.print("fn(")?;
+                self.list(b'E', Self::ty)?;
+                self.print(")")?;
+                if self.eat(b'u') {
+                    Some(())
+                } else {
+                    self.print(" -> ")?;
+                    self.ty()
+                }
+            }
+            b'D' => {
+                self.skip_tagged(b'G')?;
+                self.print("dyn ")?;
+                let mut first = true;
+                while !self.eat(b'E') {
+                    if !first {
+                        self.print(" + ")?;
+                    }
+                    first = false;
+                    self.path()?;
+                    while self.eat(b'p') {
+                        let name = self.ident()?;
+                        self.print("<")?;
+                        self.print(name)?;
+                        self.print(" = ")?;
+                        self.ty()?;
+                        self.print(">")?;
+                    }
+                }
+                if self.eat(b'L') {
+                    self.base62()?;
+                }
+                Some(())
+            }
+            b'B' => self.backref(Self::ty),
+            _ => None,
+        }
+    }
+
+    /// A constant generic argument. Only integers, `bool`, and `char` are supported.
+    fn konst(&mut self) -> Option<()> {
+        match self.next()? {
+            b'p' => self.print("_"),
+            b'B' => self.backref(Self::konst),
+            ty @ (b'a' | b'b' | b'c' | b'h' | b'i' | b'j' | b'l' | b'm' | b'n' | b'o' | b's'
+            | b't' | b'x' | b'y') => {
+                let negative = self.eat(b'n');
+                let start = self.pos;
+                while self.peek()? != b'_' {
+                    self.pos += 1;
+                }
+                let hex = core::str::from_utf8(&self.s[start..self.pos]).ok()?;
+                self.pos += 1;
+                let value = if hex.is_empty() {
+                    0
+                } else {
+                    u128::from_str_radix(hex, 16).ok()?
+                };
+                let value = match ty {
+                    b'b' => (value != 0).to_string(),
+                    b'c' => format!("{:?}", char::from_u32(u32::try_from(value).ok()?)?),
+                    _ if negative => format!("-{value}"),
+                    _ => value.to_string(),
+                };
+                self.print(&value)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy() {
+        assert_eq!(
+            demangle("_ZN4core3fmt9Formatter3pad17h0123456789abcdefE"),
+            "core::fmt::Formatter::pad"
+        );
+        assert_eq!(
+            demangle(
+                "_ZN72_$LT$ensure_simd..features..FeatureSet$u20$as$u20$core..fmt..Display$GT$3fmt17h9d1f0e3b2c4a5f60E"
+            ),
+            "<ensure_simd::features::FeatureSet as core::fmt::Display>::fmt"
+        );
+        assert_eq!(
+            demangle(
+                "_ZN3std4sync4once4Once15call_once_force28_$u7b$$u7b$closure$u7d$$u7d$17h00000000000000ffE"
+            ),
+            "std::sync::once::Once::call_once_force::{{closure}}"
+        );
+        // Without a hash.
+        assert_eq!(demangle("_ZN3foo3barE"), "foo::bar");
+    }
+
+    #[test]
+    fn v0() {
+        assert_eq!(demangle("_RNvCsgS0eDBJFJRB_3lib4main"), "lib::main");
+        assert_eq!(
+            demangle(
+                "_RNvXs_CsgS0eDBJFJRB_3libINtB4_3FoohENtNtCsgEmfK2I1SDS_4core3fmt7Display3fmt"
+            ),
+            "<lib::Foo<u8> as core::fmt::Display>::fmt"
+        );
+        assert_eq!(
+            demangle("_RNCINvNtCsjrHSEGnQ3l9_3std2rt10lang_startuE0CsgS0eDBJFJRB_3lib"),
+            "std::rt::lang_start<()>::{closure}"
+        );
+        assert_eq!(
+            demangle(
+                "_RINvNtNtCsjrHSEGnQ3l9_3std3sys9backtrace28___rust_begin_short_backtraceFEuuECsgS0eDBJFJRB_3lib"
+            ),
+            "std::sys::backtrace::__rust_begin_short_backtrace<fn(), ()>"
+        );
+        // The suffix added by LLVM is dropped.
+        assert_eq!(
+            demangle("_RNvCsgS0eDBJFJRB_3lib4main.llvm.1234"),
+            "lib::main"
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        for name in [
+            "main",
+            "_ZN",
+            "_ZN5fooE",
+            // The length overflows when added to the number of digits.
+            "_ZN18446744073709551615fooE",
+            "_R",
+            "_RNvC",
+            "_RNvCsgS0eDBJFJRB_3lib99main",
+            // A backref to itself.
+            "_RB_",
+        ] {
+            assert_eq!(demangle(name), name);
+        }
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let name = format!("_R{}C3foo", "NvNv".repeat(MAX_DEPTH as usize));
+        assert_eq!(demangle(&name), name);
+    }
+}
@@ -0,0 +1,228 @@
+//! Pluggable feature detection, so that tests can simulate other CPUs.
+
+use crate::features::Cpu;
+use crate::{FeatureSet, SimdError, SimdLevel};
+
+/// A source of the features supported by the CPU.
+///
+/// [`HostCpu`] asks the actual CPU, and [`MockCpu`] returns a fixed set of features.
+/// Use [`check_simd_with`] to check against a detector for a single call,
+/// or [`with_detector`] to override detection for all checks on the current thread.
+pub trait FeatureDetector {
+    /// The features supported by the CPU.
+    fn detect(&self) -> FeatureSet;
+}
+
+/// The CPU running this process, as detected using `cpuid` on x86 and `hwcap` on aarch64.
+///
+/// Unlike [`FeatureSet::detected`], this ignores overrides by [`with_detector`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HostCpu;
+
+impl FeatureDetector for HostCpu {
+    fn detect(&self) -> FeatureSet {
+        FeatureSet::from_cpu(&Cpu::read())
+    }
+}
+
+/// A simulated CPU with a fixed set of features, for testing.
+///
+/// Features that are not known on the current architecture are ignored,
+/// so e.g. [`MockCpu::GRAVITON2`] only has `aes` on x86.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MockCpu {
+    /// A human-readable name, e.g. `Haswell`.
+    pub name: &'static str,
+    /// The features the simulated CPU supports.
+    pub features: FeatureSet,
+}
+
+impl FeatureDetector for MockCpu {
+    fn detect(&self) -> FeatureSet {
+        self.features
+    }
+}
+
+impl MockCpu {
+    /// A CPU with the given target features, e.g. `&["sse4.2", "popcnt"]`.
+    pub const fn new(name: &'static str, features: &[&str]) -> Self {
+        Self {
+            name,
+            features: FeatureSet::from_known_names(features),
+        }
+    }
+
+    /// Intel Nehalem (2008): `x86-64-v2`, without AVX.
+    #[rustfmt::skip]
+    pub const NEHALEM: Self = Self::new("Nehalem", &[
+        "sse", "sse2", "fxsr", "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "cmpxchg16b",
+    ]);
+
+    /// Intel Haswell (2013): `x86-64-v3`, the first CPU with AVX2.
+    #[rustfmt::skip]
+    pub const HASWELL: Self = Self::new("Haswell", &[
+        "sse", "sse2", "fxsr", "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "cmpxchg16b",
+        "avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "movbe", "xsave",
+        "aes", "pclmulqdq", "rdrand", "xsaveopt",
+    ]);
+
+    /// AMD Zen 1 (2017): `x86-64-v3`, with SHA and SSE4a but no AVX-512.
+    #[rustfmt::skip]
+    pub const ZEN1: Self = Self::new("Zen 1", &[
+        "sse", "sse2", "fxsr", "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "cmpxchg16b",
+        "avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "movbe", "xsave",
+        "sse4a", "adx", "aes", "pclmulqdq", "sha", "rdrand", "rdseed", "xsaveopt", "xsavec", "xsaves",
+    ]);
+
+    /// AWS Graviton 2 (Neoverse N1): NEON, without SVE.
+    #[rustfmt::skip]
+    pub const GRAVITON2: Self = Self::new("Graviton 2", &[
+        "neon", "aes", "sha2", "crc", "lse", "fp16", "rdm", "rcpc", "dpb", "dotprod", "ssbs",
+    ]);
+
+    /// AWS Graviton 3 (Neoverse V1): SVE, without SVE2.
+    #[rustfmt::skip]
+    pub const GRAVITON3: Self = Self::new("Graviton 3", &[
+        "neon", "aes", "sha2", "crc", "lse", "fp16", "rdm", "rcpc", "dpb", "dotprod", "ssbs",
+        "sve", "sha3", "sm4", "lse2", "jsconv", "fcma", "rcpc2", "dpb2", "fhm", "dit", "flagm",
+        "paca", "pacg", "i8mm", "bf16", "rand",
+    ]);
+}
+
+/// Like [`check_simd`](crate::check_simd), but with the features of the given detector.
+pub fn check_simd_with(detector: &impl FeatureDetector) -> Result<(), SimdError> {
+    crate::check_features(crate::check_simd_required(), detector.detect())
+}
+
+/// Like [`check_simd_level`](crate::check_simd_level), but with the features of the given detector.
+pub fn check_simd_level_with(
+    level: SimdLevel,
+    detector: &impl FeatureDetector,
+) -> Result<(), SimdError> {
+    crate::check_features(crate::check_simd_level_required(level), detector.detect())
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static OVERRIDE: core::cell::Cell<Option<FeatureSet>> = const { core::cell::Cell::new(None) };
+}
+
+/// The features set by [`with_detector`] on the current thread, if any.
+#[cfg(feature = "std")]
+pub(crate) fn thread_override() -> Option<FeatureSet> {
+    OVERRIDE.get()
+}
+
+/// Run `f` with all detection on the current thread, including [`FeatureSet::detected`],
+/// [`check_simd`](crate::check_simd), and [`ensure_simd`](crate::ensure_simd), using the given detector.
+///
+/// The detector is queried once, when `f` starts. The previous detection is restored when `f`
+/// returns or panics, so calls can be nested.
+///
+/// ```
+/// use ensure_simd::{MockCpu, SimdLevel, with_detector};
+///
+/// # #[cfg(target_arch = "x86_64")]
+/// with_detector(&MockCpu::NEHALEM, || {
+///     assert_eq!(SimdLevel::detected(), SimdLevel::X86_64V2);
+/// });
+/// ```
+#[cfg(feature = "std")]
+pub fn with_detector<R>(detector: &impl FeatureDetector, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<FeatureSet>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            OVERRIDE.set(self.0);
+        }
+    }
+    let _restore = Restore(OVERRIDE.replace(Some(detector.detect())));
+    f()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    /// A CPU with all known features.
+    const ALL: MockCpu = MockCpu {
+        name: "all",
+        features: FeatureSet::ALL,
+    };
+
+    #[test]
+    fn all_features() {
+        assert_eq!(check_simd_with(&ALL), Ok(()));
+    }
+
+    #[test]
+    #[cfg(target_feature = "avx2")]
+    fn nehalem() {
+        let err = check_simd_with(&MockCpu::NEHALEM).unwrap_err();
+        assert_eq!(err.detected(), MockCpu::NEHALEM.features);
+        assert!(err.required().is_superset(&FeatureSet::compiled()));
+
+        let missing = err.missing();
+        assert!(missing.contains("avx"));
+        assert!(missing.contains("avx2"));
+        assert!(!missing.contains("sse4.2"));
+        assert!(MockCpu::NEHALEM.features.intersection(&missing).is_empty());
+
+        let message = err.to_string();
+        assert!(
+            message.contains(
+                "This binary requires SIMD instructions that your CPU does not support: "
+            ),
+            "{message}"
+        );
+        assert!(message.contains("avx2"), "{message}");
+        assert!(!message.contains("sse4.2"), "{message}");
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn haswell() {
+        let check =
+            |level| check_simd_level_with(level, &MockCpu::HASWELL).map_err(|err| err.missing());
+        // Features compiled in beyond Haswell, e.g. with `-C target-cpu=native`, are always missing.
+        let beyond = FeatureSet::compiled().difference(&MockCpu::HASWELL.features);
+        let expected = |missing: FeatureSet| {
+            if missing.is_empty() {
+                Ok(())
+            } else {
+                Err(missing)
+            }
+        };
+        assert_eq!(check(SimdLevel::X86_64V3), expected(beyond));
+        // Debug builds do not require the level's features, only those compiled in.
+        let avx512 = if cfg!(debug_assertions) {
+            FeatureSet::EMPTY
+        } else {
+            FeatureSet::from_names(&["avx512f", "avx512bw", "avx512cd", "avx512dq", "avx512vl"])
+        };
+        assert_eq!(check(SimdLevel::X86_64V4), expected(beyond.union(&avx512)));
+    }
+
+    #[test]
+    fn with_detector_nests_and_restores() {
+        let outer = FeatureSet::detected();
+        with_detector(&MockCpu::NEHALEM, || {
+            assert_eq!(FeatureSet::detected(), MockCpu::NEHALEM.features);
+            with_detector(&ALL, || assert_eq!(FeatureSet::detected(), FeatureSet::ALL));
+            assert_eq!(FeatureSet::detected(), MockCpu::NEHALEM.features);
+        });
+        assert_eq!(FeatureSet::detected(), outer);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn mock_cpus_ignore_other_architectures() {
+        assert_eq!(
+            MockCpu::GRAVITON2.features,
+            FeatureSet::from_names(&["aes"])
+        );
+        assert_eq!(
+            MockCpu::new("mixed", &["neon", "avx2"]).features,
+            FeatureSet::from_names(&["avx2"])
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Run-time support for `#[ensure_simd::multiversion]`.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::{FeatureDetector, FeatureSet, HostCpu, SimdLevel};
+
+/// The level to dispatch to, detected on first use.
+pub struct Dispatch(AtomicU8);
+
+impl Dispatch {
+    /// Not yet detected.
+    const UNKNOWN: u8 = u8::MAX;
+
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self(AtomicU8::new(Self::UNKNOWN))
+    }
+
+    /// The best level supported by the CPU.
+    ///
+    /// This respects the `ENSURE_SIMD_LEVEL` and `ENSURE_SIMD_DISABLE` environment variables and
+    /// [`with_detector`](crate::with_detector) at the first call, but never exceeds the actual CPU,
+    /// since running a clone for a level the CPU does not support is undefined behaviour.
+    pub fn level(&self) -> SimdLevel {
+        let level = self.0.load(Ordering::Relaxed);
+        if level != Self::UNKNOWN {
+            return SimdLevel::ALL[level as usize];
+        }
+        let features = FeatureSet::detected().intersection(&HostCpu.detect());
+        let level = SimdLevel::best_for(features);
+        self.0.store(level as u8, Ordering::Relaxed);
+        level
+    }
+}
+
+/// Whether `features` is the comma-separated list of [`SimdLevel::target_features`].
+///
+/// Used to check at compile time that the feature lists of the macros are in sync with this crate.
+pub const fn has_target_features(level: SimdLevel, features: &str) -> bool {
+    let features = features.as_bytes();
+    let expected = level.target_features();
+    let mut pos = 0;
+    let mut i = 0;
+    while i < expected.len() {
+        if i > 0 {
+            if pos >= features.len() || features[pos] != b',' {
+                return false;
+            }
+            pos += 1;
+        }
+        let name = expected[i].as_bytes();
+        let mut j = 0;
+        while j < name.len() {
+            if pos >= features.len() || features[pos] != name[j] {
+                return false;
+            }
+            pos += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+    pos == features.len()
+}
@@ -0,0 +1,528 @@
+//! A minimal ELF parser, for reading the build metadata and code of other binaries.
+//!
+//! Supports 32 and 64-bit files of either endianness. Only section headers are used.
+
+/// A section header.
+pub(crate) struct Section<'a> {
+    pub name: &'a str,
+    pub ty: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+}
+
+/// An ELF note, as `(owner, type, description)`.
+pub(crate) type Note<'a> = (&'a str, u32, &'a [u8]);
+
+/// A function symbol.
+pub(crate) struct Symbol<'a> {
+    pub name: &'a str,
+    /// The index of the section containing the function.
+    pub section: usize,
+    /// The offset of the function in its section.
+    pub offset: u64,
+    pub size: u64,
+}
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+pub(crate) const SHT_NOTE: u32 = 7;
+const SHT_DYNSYM: u32 = 11;
+const SHF_EXECINSTR: u64 = 4;
+pub(crate) const EM_X86_64: u16 = 62;
+pub(crate) const EM_AARCH64: u16 = 183;
+const ET_REL: u16 = 1;
+const STT_FUNC: u8 = 2;
+
+/// A parsed ELF file.
+pub(crate) struct Elf<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    little_endian: bool,
+    /// Whether this is an object file, in which symbols are relative to their section.
+    relocatable: bool,
+    /// The `e_machine` field, e.g. 62 for x86-64.
+    pub machine: u16,
+    pub sections: Vec<Section<'a>>,
+}
+
+impl<'a> Elf<'a> {
+    /// Parse the headers, returning `None` when `data` is not a valid ELF file.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.get(..4)? != b"\x7fELF" {
+            return None;
+        }
+        let mut elf = Elf {
+            data,
+            is_64: match data.get(4)? {
+                1 => false,
+                2 => true,
+                _ => return None,
+            },
+            little_endian: match data.get(5)? {
+                1 => true,
+                2 => false,
+                _ => return None,
+            },
+            relocatable: false,
+            machine: 0,
+            sections: vec![],
+        };
+        elf.relocatable = elf.u16(16)? == ET_REL;
+        elf.machine = elf.u16(18)?;
+        let (shoff, shentsize, shnum, shstrndx) = if elf.is_64 {
+            (
+                elf.u64(0x28)?,
+                elf.u16(0x3a)?,
+                elf.u16(0x3c)?,
+                elf.u16(0x3e)?,
+            )
+        } else {
+            (
+                elf.u32(0x20)? as u64,
+                elf.u16(0x2e)?,
+                elf.u16(0x30)?,
+                elf.u16(0x32)?,
+            )
+        };
+
+        // The raw headers, with the offset of the name in the section name table.
+        let mut headers = vec![];
+        for i in 0..shnum as u64 {
+            let h = usize::try_from(shoff.checked_add(i * shentsize as u64)?).ok()?;
+            let header = if elf.is_64 {
+                (
+                    elf.u32(h)?,
+                    Section {
+                        name: "",
+                        ty: elf.u32(h + 4)?,
+                        flags: elf.u64(h + 8)?,
+                        addr: elf.u64(h + 0x10)?,
+                        offset: elf.u64(h + 0x18)?,
+                        size: elf.u64(h + 0x20)?,
+                        link: elf.u32(h + 0x28)?,
+                    },
+                )
+            } else {
+                (
+                    elf.u32(h)?,
+                    Section {
+                        name: "",
+                        ty: elf.u32(h + 4)?,
+                        flags: elf.u32(h + 8)? as u64,
+                        addr: elf.u32(h + 0xc)? as u64,
+                        offset: elf.u32(h + 0x10)? as u64,
+                        size: elf.u32(h + 0x14)? as u64,
+                        link: elf.u32(h + 0x18)?,
+                    },
+                )
+            };
+            headers.push(header);
+        }
+        let names = headers
+            .get(shstrndx as usize)
+            .and_then(|(_, s)| elf.data_of(s));
+        for (name, mut section) in headers {
+            section.name = names.and_then(|n| str_at(n, name as usize)).unwrap_or("");
+            elf.sections.push(section);
+        }
+        Some(elf)
+    }
+
+    /// The contents of a section.
+    pub fn data_of(&self, section: &Section) -> Option<&'a [u8]> {
+        let start = usize::try_from(section.offset).ok()?;
+        let end = start.checked_add(usize::try_from(section.size).ok()?)?;
+        self.data.get(start..end)
+    }
+
+    /// The function symbols, from `.symtab`, or from `.dynsym` for stripped binaries.
+    fn functions(&self) -> Vec<Symbol<'a>> {
+        let table = self
+            .sections
+            .iter()
+            .find(|s| s.ty == SHT_SYMTAB)
+            .or_else(|| self.sections.iter().find(|s| s.ty == SHT_DYNSYM));
+        let Some(table) = table else {
+            return vec![];
+        };
+        let (Some(data), Some(names)) = (
+            self.data_of(table),
+            self.sections
+                .get(table.link as usize)
+                .and_then(|s| self.data_of(s)),
+        ) else {
+            return vec![];
+        };
+        let entsize = if self.is_64 { 24 } else { 16 };
+        let mut symbols = vec![];
+        for entry in data.chunks_exact(entsize) {
+            let u16 = |pos| self.u16_in(entry, pos);
+            let u32 = |pos| self.u32_in(entry, pos);
+            let (name, info, section, value, size) = if self.is_64 {
+                let u64 = |pos| self.u64_in(entry, pos);
+                (u32(0), entry[4], u16(6), u64(8), u64(16))
+            } else {
+                let u32_64 = |pos| u32(pos).map(u64::from);
+                (u32(0), entry[12], u16(14), u32_64(4), u32_64(8))
+            };
+            let (Some(name), Some(section), Some(value), Some(size)) = (name, section, value, size)
+            else {
+                continue;
+            };
+            let Some(s) = self.sections.get(section as usize) else {
+                continue;
+            };
+            if info & 0xf != STT_FUNC || size == 0 {
+                continue;
+            }
+            symbols.push(Symbol {
+                name: str_at(names, name as usize).unwrap_or(""),
+                section: section as usize,
+                offset: if self.relocatable {
+                    value
+                } else {
+                    value.wrapping_sub(s.addr)
+                },
+                size,
+            });
+        }
+        symbols
+    }
+
+    /// The code of each function in the executable sections, as `(name, code)`.
+    ///
+    /// Code outside all functions, such as padding, or functions without symbol in stripped
+    /// binaries, is attributed to the section, e.g. `.text`.
+    pub fn function_code(&self) -> Vec<(&'a str, &'a [u8])> {
+        let functions = self.functions();
+        let mut ranges = vec![];
+        for (index, section) in self.sections.iter().enumerate() {
+            if section.ty != SHT_PROGBITS || section.flags & SHF_EXECINSTR == 0 {
+                continue;
+            }
+            let Some(code) = self.data_of(section) else {
+                continue;
+            };
+            let mut starts: Vec<(usize, usize, &str)> = functions
+                .iter()
+                .filter(|f| f.section == index && f.offset < code.len() as u64)
+                .map(|f| (f.offset as usize, f.size as usize, f.name))
+                .collect();
+            starts.sort_by_key(|&(offset, _, _)| offset);
+            starts.dedup_by_key(|&mut (offset, _, _)| offset);
+            let mut pos = 0;
+            for (j, &(start, size, name)) in starts.iter().enumerate() {
+                let next = starts
+                    .get(j + 1)
+                    .map_or(code.len(), |&(offset, _, _)| offset);
+                if pos < start {
+                    ranges.push((section.name, &code[pos..start]));
+                }
+                pos = next.min(start.saturating_add(size));
+                ranges.push((name, &code[start..pos]));
+            }
+            if pos < code.len() {
+                ranges.push((section.name, &code[pos..]));
+            }
+        }
+        ranges
+    }
+
+    /// All notes in `SHT_NOTE` sections.
+    pub fn notes(&self) -> Vec<Note<'a>> {
+        let mut notes = vec![];
+        for section in self.sections.iter().filter(|s| s.ty == SHT_NOTE) {
+            let Some(data) = self.data_of(section) else {
+                continue;
+            };
+            let mut pos = 0;
+            while let Some((note, next)) = self.note_at(data, pos) {
+                notes.push(note);
+                pos = next;
+            }
+        }
+        notes
+    }
+
+    /// The note at `pos` in the contents of a note section, and the position of the next note.
+    fn note_at(&self, data: &'a [u8], pos: usize) -> Option<(Note<'a>, usize)> {
+        let namesz = self.u32_in(data, pos)? as usize;
+        let descsz = self.u32_in(data, pos.checked_add(4)?)? as usize;
+        let ty = self.u32_in(data, pos.checked_add(8)?)?;
+        let name_start = pos.checked_add(12)?;
+        let desc_start = name_start.checked_add(namesz.checked_next_multiple_of(4)?)?;
+        let next = desc_start.checked_add(descsz.checked_next_multiple_of(4)?)?;
+        let name = data.get(name_start..name_start.checked_add(namesz)?)?;
+        let desc = data.get(desc_start..desc_start.checked_add(descsz)?)?;
+        let name = core::str::from_utf8(name).unwrap_or("");
+        Some(((name.trim_end_matches('\0'), ty, desc), next))
+    }
+
+    pub fn u16(&self, pos: usize) -> Option<u16> {
+        self.u16_in(self.data, pos)
+    }
+
+    pub fn u32(&self, pos: usize) -> Option<u32> {
+        self.u32_in(self.data, pos)
+    }
+
+    pub fn u64(&self, pos: usize) -> Option<u64> {
+        self.u64_in(self.data, pos)
+    }
+
+    fn u16_in(&self, data: &[u8], pos: usize) -> Option<u16> {
+        let b = data.get(pos..pos.checked_add(2)?)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    }
+
+    fn u32_in(&self, data: &[u8], pos: usize) -> Option<u32> {
+        let b = data.get(pos..pos.checked_add(4)?)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn u64_in(&self, data: &[u8], pos: usize) -> Option<u64> {
+        let b = data.get(pos..pos.checked_add(8)?)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u64::from_le_bytes(b)
+        } else {
+            u64::from_be_bytes(b)
+        })
+    }
+}
+
+/// The NUL-terminated string at `pos` in a string table.
+pub(crate) fn str_at(table: &[u8], pos: usize) -> Option<&str> {
+    let bytes = table.get(pos..)?;
+    let end = bytes.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&bytes[..end]).ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A section for [`build`]: `(name, type, flags, link, contents)`.
+    pub(crate) type TestSection<'a> = (&'a str, u32, u64, u32, &'a [u8]);
+
+    /// Build a 64-bit little-endian executable with the given sections.
+    ///
+    /// They follow the null section and `.shstrtab`, so the first has index 2.
+    /// Each section is loaded at its offset in the file.
+    pub(crate) fn build(machine: u16, sections: &[TestSection]) -> Vec<u8> {
+        let mut shstrtab = b"\0.shstrtab\0".to_vec();
+        let mut data = vec![0; 64];
+        let mut headers = vec![[0; 64], [0; 64]];
+        for &(name, ty, flags, link, contents) in sections {
+            headers.push(header(
+                &mut data,
+                shstrtab.len() as u32,
+                ty,
+                flags,
+                link,
+                contents,
+            ));
+            shstrtab.extend_from_slice(name.as_bytes());
+            shstrtab.push(0);
+        }
+        headers[1] = header(&mut data, 1, 3, 0, 0, &shstrtab);
+
+        data.resize(data.len().next_multiple_of(8), 0);
+        let shoff = data.len() as u64;
+        for h in &headers {
+            data.extend_from_slice(h);
+        }
+        data[..8].copy_from_slice(b"\x7fELF\x02\x01\x01\0");
+        data[16..18].copy_from_slice(&2u16.to_le_bytes());
+        data[18..20].copy_from_slice(&machine.to_le_bytes());
+        data[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        data[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        data[0x3c..0x3e].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+        data[0x3e..0x40].copy_from_slice(&1u16.to_le_bytes());
+        data
+    }
+
+    /// Append `contents` to `data`, and return its section header.
+    fn header(
+        data: &mut Vec<u8>,
+        name: u32,
+        ty: u32,
+        flags: u64,
+        link: u32,
+        contents: &[u8],
+    ) -> [u8; 64] {
+        data.resize(data.len().next_multiple_of(8), 0);
+        let offset = data.len() as u64;
+        data.extend_from_slice(contents);
+        let mut h = [0; 64];
+        h[0..4].copy_from_slice(&name.to_le_bytes());
+        h[4..8].copy_from_slice(&ty.to_le_bytes());
+        h[8..0x10].copy_from_slice(&flags.to_le_bytes());
+        h[0x10..0x18].copy_from_slice(&offset.to_le_bytes());
+        h[0x18..0x20].copy_from_slice(&offset.to_le_bytes());
+        h[0x20..0x28].copy_from_slice(&(contents.len() as u64).to_le_bytes());
+        h[0x28..0x2c].copy_from_slice(&link.to_le_bytes());
+        h
+    }
+
+    /// An ELF note, with the name and description padded to 4 bytes.
+    pub(crate) fn note(owner: &str, ty: u32, desc: &[u8]) -> Vec<u8> {
+        let mut note = vec![];
+        note.extend_from_slice(&(owner.len() as u32 + 1).to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&ty.to_le_bytes());
+        note.extend_from_slice(owner.as_bytes());
+        note.push(0);
+        note.resize(note.len().next_multiple_of(4), 0);
+        note.extend_from_slice(desc);
+        note.resize(note.len().next_multiple_of(4), 0);
+        note
+    }
+
+    #[test]
+    fn parse() {
+        let data = build(
+            EM_X86_64,
+            &[(".text", SHT_PROGBITS, SHF_EXECINSTR, 0, &[0x90; 3])],
+        );
+        let elf = Elf::parse(&data).unwrap();
+        assert_eq!(elf.machine, EM_X86_64);
+        let names: Vec<_> = elf.sections.iter().map(|s| s.name).collect();
+        assert_eq!(names, ["", ".shstrtab", ".text"]);
+        assert_eq!(elf.data_of(&elf.sections[2]), Some(&[0x90; 3][..]));
+    }
+
+    #[test]
+    fn not_elf() {
+        assert!(Elf::parse(b"").is_none());
+        assert!(Elf::parse(b"MZ\x90\0").is_none());
+        assert!(Elf::parse(b"\x7fELF\x02\x01\x01").is_none());
+        let mut data = build(EM_X86_64, &[]);
+        data[4] = 3;
+        assert!(Elf::parse(&data).is_none());
+    }
+
+    #[test]
+    fn notes() {
+        let mut section = note("GNU", 3, &[1, 2, 3, 4, 5]);
+        section.extend(note("ensure_simd", 1, b"version=1\n"));
+        let data = build(
+            EM_X86_64,
+            &[
+                (".note.gnu.build-id", SHT_NOTE, 0, 0, &section),
+                // Not a note section, so ignored.
+                (".data", SHT_PROGBITS, 0, 0, &note("other", 1, b"")),
+            ],
+        );
+        let notes = Elf::parse(&data).unwrap().notes();
+        assert_eq!(
+            notes,
+            [
+                ("GNU", 3, &[1, 2, 3, 4, 5][..]),
+                ("ensure_simd", 1, &b"version=1\n"[..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn oversized_notes() {
+        for (namesz, descsz) in [(u32::MAX, 0), (4, u32::MAX), (u32::MAX - 2, u32::MAX - 2)] {
+            let mut section = vec![];
+            for x in [namesz, descsz, 1] {
+                section.extend_from_slice(&x.to_le_bytes());
+            }
+            section.extend_from_slice(b"GNU\0");
+            let data = build(EM_X86_64, &[(".note", SHT_NOTE, 0, 0, &section)]);
+            assert_eq!(Elf::parse(&data).unwrap().notes(), []);
+        }
+    }
+
+    /// A 64-bit symbol table entry.
+    fn symbol(name: u32, ty: u8, section: u16, value: u64, size: u64) -> [u8; 24] {
+        let mut sym = [0; 24];
+        sym[0..4].copy_from_slice(&name.to_le_bytes());
+        sym[4] = ty;
+        sym[6..8].copy_from_slice(&section.to_le_bytes());
+        sym[8..16].copy_from_slice(&value.to_le_bytes());
+        sym[16..24].copy_from_slice(&size.to_le_bytes());
+        sym
+    }
+
+    /// The function ranges of `data`, as offsets into the code of their section.
+    fn ranges(data: &[u8]) -> Vec<(&str, core::ops::Range<usize>)> {
+        let elf = Elf::parse(data).unwrap();
+        let text = elf.data_of(&elf.sections[2]).unwrap();
+        elf.function_code()
+            .into_iter()
+            .map(|(name, code)| {
+                let start = code.as_ptr() as usize - text.as_ptr() as usize;
+                (name, start..start + code.len())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn function_code() {
+        // `.text` is loaded at its file offset, right after the ELF header.
+        let text = 64;
+        let symbols = [
+            symbol(0, 0, 0, 0, 0),
+            symbol(1, STT_FUNC, 2, text, 4),
+            // An alias of `a`.
+            symbol(3, STT_FUNC, 2, text, 4),
+            symbol(5, STT_FUNC, 2, text + 6, 4),
+            // Extends past the end of the section.
+            symbol(7, STT_FUNC, 2, text + 10, 100),
+            // Not a function, without size, or in another section.
+            symbol(9, 1, 2, text + 4, 2),
+            symbol(9, STT_FUNC, 2, text + 4, 0),
+            symbol(9, STT_FUNC, 3, text + 4, 2),
+        ];
+        let data = build(
+            EM_X86_64,
+            &[
+                (".text", SHT_PROGBITS, SHF_EXECINSTR, 0, &[0x90; 16]),
+                (".symtab", SHT_SYMTAB, 0, 4, symbols.as_flattened()),
+                (".strtab", 3, 0, 0, b"\0a\0d\0b\0c\0x\0"),
+            ],
+        );
+        assert_eq!(
+            ranges(&data),
+            [("a", 0..4), (".text", 4..6), ("b", 6..10), ("c", 10..16)]
+        );
+    }
+
+    #[test]
+    fn function_code_stripped() {
+        let data = build(
+            EM_X86_64,
+            &[
+                (".text", SHT_PROGBITS, SHF_EXECINSTR, 0, &[0x90; 16]),
+                // Not executable, so ignored.
+                (".rodata", SHT_PROGBITS, 0, 0, &[0; 8]),
+            ],
+        );
+        assert_eq!(ranges(&data), [(".text", 0..16)]);
+    }
+
+    #[test]
+    fn truncated_notes() {
+        let mut section = note("GNU", 3, &[1, 2, 3, 4]);
+        let mut truncated = note("ensure_simd", 1, b"version=1\n");
+        truncated.truncate(truncated.len() - 4);
+        section.extend(truncated);
+        let data = build(EM_X86_64, &[(".note", SHT_NOTE, 0, 0, &section)]);
+        let notes = Elf::parse(&data).unwrap().notes();
+        assert_eq!(notes, [("GNU", 3, &[1, 2, 3, 4][..])]);
+    }
+}
@@ -0,0 +1,107 @@
+//! Environment variables that override the run-time check, for benchmarking and reproducing bug reports.
+//!
+//! - `ENSURE_SIMD_LEVEL=v2`: hide all detected features above the given [`SimdLevel`].
+//! - `ENSURE_SIMD_DISABLE=avx512f,avx2`: hide the given features.
+//! - `ENSURE_SIMD_SKIP=1`: skip the check in [`ensure_simd`](crate::ensure_simd).
+
+use std::sync::OnceLock;
+
+use crate::{FeatureSet, SimdLevel};
+
+const LEVEL: &str = "ENSURE_SIMD_LEVEL";
+const DISABLE: &str = "ENSURE_SIMD_DISABLE";
+const SKIP: &str = "ENSURE_SIMD_SKIP";
+
+/// The detected features that are not hidden by `ENSURE_SIMD_LEVEL` and `ENSURE_SIMD_DISABLE`.
+///
+/// Read once per process, warning about invalid values.
+pub(crate) fn allowed() -> FeatureSet {
+    static ALLOWED: OnceLock<FeatureSet> = OnceLock::new();
+    *ALLOWED.get_or_init(|| {
+        parse_allowed(
+            std::env::var(LEVEL).ok().as_deref(),
+            std::env::var(DISABLE).ok().as_deref(),
+        )
+    })
+}
+
+/// The features not hidden by the given values of `ENSURE_SIMD_LEVEL` and `ENSURE_SIMD_DISABLE`.
+fn parse_allowed(level: Option<&str>, disable: Option<&str>) -> FeatureSet {
+    let mut allowed = FeatureSet::ALL;
+    if let Some(level) = level {
+        match level.parse::<SimdLevel>() {
+            Ok(level) => allowed = level.feature_set(),
+            Err(err) => eprintln!("Warning: ignoring {LEVEL}={level}: {err}."),
+        }
+    }
+    if let Some(disable) = disable {
+        for name in disable.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let feature = FeatureSet::from_known_names(&[name]);
+            if feature.is_empty() {
+                eprintln!("Warning: ignoring unknown target feature `{name}` in {DISABLE}.");
+            }
+            allowed = allowed.difference(&feature);
+        }
+    }
+    allowed
+}
+
+/// Whether `ENSURE_SIMD_SKIP` is set to a non-empty value other than `0`.
+pub(crate) fn skip() -> bool {
+    std::env::var_os(SKIP).is_some_and(|v| !v.is_empty() && v != "0")
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset() {
+        assert_eq!(parse_allowed(None, None), FeatureSet::ALL);
+    }
+
+    #[test]
+    fn level() {
+        let allowed = parse_allowed(Some("v2"), None);
+        assert_eq!(allowed, SimdLevel::X86_64V2.feature_set());
+        assert!(allowed.contains("sse4.2"));
+        assert!(!allowed.contains("avx2"));
+        assert_eq!(
+            parse_allowed(Some("x86-64-v3"), None),
+            SimdLevel::X86_64V3.feature_set()
+        );
+    }
+
+    #[test]
+    fn invalid_level_is_ignored() {
+        assert_eq!(parse_allowed(Some("v5"), None), FeatureSet::ALL);
+    }
+
+    #[test]
+    fn disable() {
+        let allowed = parse_allowed(None, Some("avx512f, avx2,,"));
+        assert!(!allowed.contains("avx512f"));
+        assert!(!allowed.contains("avx2"));
+        assert!(allowed.contains("avx"));
+        assert_eq!(
+            allowed.union(&FeatureSet::from_names(&["avx2", "avx512f"])),
+            FeatureSet::ALL
+        );
+    }
+
+    #[test]
+    fn unknown_features_are_ignored() {
+        assert_eq!(parse_allowed(None, Some("neon,avx3")), FeatureSet::ALL);
+    }
+
+    #[test]
+    fn level_and_disable() {
+        let allowed = parse_allowed(Some("v3"), Some("fma"));
+        assert_eq!(
+            allowed,
+            SimdLevel::X86_64V3
+                .feature_set()
+                .difference(&FeatureSet::from_names(&["fma"]))
+        );
+    }
+}
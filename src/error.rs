@@ -0,0 +1,113 @@
+use crate::FeatureSet;
+#[cfg(feature = "std")]
+use crate::{SimdLevel, compile_check::Message};
+
+/// A value from the build metadata of the ELF note.
+fn note_value(key: &str) -> &'static str {
+    crate::note::build_note_value(key).unwrap_or("unknown")
+}
+
+/// Error returned by [`check_simd`](crate::check_simd) when the CPU does not support
+/// all target features that were enabled at compile time, or those of the required [`SimdLevel`](crate::SimdLevel).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SimdError {
+    pub(crate) required: FeatureSet,
+    pub(crate) detected: FeatureSet,
+}
+
+impl SimdError {
+    /// The target features the binary was compiled with, and those of the required level.
+    pub fn required(&self) -> FeatureSet {
+        self.required
+    }
+
+    /// The target features supported by the current CPU.
+    pub fn detected(&self) -> FeatureSet {
+        self.detected
+    }
+
+    /// The required target features that are not supported by the current CPU.
+    pub fn missing(&self) -> FeatureSet {
+        self.required.difference(&self.detected)
+    }
+
+    /// The error for a CPU that does not support all target features enabled at compile time,
+    /// found by [`compiled_supported`](crate::features::compiled_supported).
+    #[cfg(feature = "load-check")]
+    #[cold]
+    #[inline(never)]
+    pub(crate) fn unsupported() -> Self {
+        Self {
+            required: FeatureSet::compiled(),
+            detected: FeatureSet::detected(),
+        }
+    }
+
+    #[inline(never)]
+    fn fmt_missing(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Formatted as `CpuFingerprint::build()`, without allocating.
+        #[cfg(ensure_simd_native)]
+        writeln!(
+            f,
+            "This binary was built with `-C target-cpu=native` for a different CPU: {} ({}).",
+            env!("ENSURE_SIMD_BUILD_CPU_MODEL"),
+            env!("ENSURE_SIMD_BUILD_CPU_VENDOR"),
+        )?;
+        write!(
+            f,
+            "This binary requires SIMD instructions that your CPU does not support: {}.
+It was compiled for {} on `{}` using {}.
+Please run on a CPU that supports these, rebuild on this machine using RUSTFLAGS=\"-C target-cpu=native\",
+or build from source with the `-F scalar` feature enabled.
+See the readme at https://github.com/ragnargrootkoerkamp/ensure_simd for details.",
+            self.missing(),
+            note_value("level"),
+            note_value("target"),
+            note_value("rustc"),
+        )
+    }
+}
+
+impl core::fmt::Display for SimdError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Listing the missing features runs code that may use the enabled target features.
+        #[cfg(feature = "std")]
+        if !crate::features::compiled_supported() {
+            return f.write_str(UNSUPPORTED);
+        }
+        self.fmt_missing(f)
+    }
+}
+
+impl core::error::Error for SimdError {}
+
+/// The error message for a CPU that does not support all target features enabled at compile time.
+///
+/// Unlike the [`Display`](core::fmt::Display) of [`SimdError`], it is rendered at compile time,
+/// so that printing it does not run code that uses those features.
+/// It lists all enabled features, since the missing ones are not computed.
+#[cfg(feature = "std")]
+pub(crate) const UNSUPPORTED: &str = UNSUPPORTED_MESSAGE.as_str();
+
+#[cfg(feature = "std")]
+const UNSUPPORTED_MESSAGE: Message = {
+    let mut m = Message::new();
+    #[cfg(ensure_simd_native)]
+    {
+        m.push("This binary was built with `-C target-cpu=native` for a different CPU: ");
+        m.push(env!("ENSURE_SIMD_BUILD_CPU_MODEL"));
+        m.push(" (");
+        m.push(env!("ENSURE_SIMD_BUILD_CPU_VENDOR"));
+        m.push(").\n");
+    }
+    m.push("This binary requires SIMD instructions that your CPU does not support.\nIt was compiled for ");
+    m.push(SimdLevel::compiled().name());
+    m.push(" on `");
+    m.push(env!("ENSURE_SIMD_TARGET"));
+    m.push("` using ");
+    m.push(env!("ENSURE_SIMD_RUSTC_VERSION"));
+    m.push(", with target features: ");
+    m.push_features(FeatureSet::compiled(), "", ", ");
+    m.push(".\nPlease run on a CPU that supports these, rebuild on this machine using RUSTFLAGS=\"-C target-cpu=native\",\nor build from source with the `-F scalar` feature enabled.\nSee the readme at https://github.com/ragnargrootkoerkamp/ensure_simd for details.");
+    m
+};
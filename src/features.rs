@@ -0,0 +1,427 @@
+//! Table of target features that are checked at run time.
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::cpuid as arch;
+#[cfg(target_arch = "aarch64")]
+use crate::hwcap as arch;
+
+/// A target feature that may be enabled at compile time and detected at run time.
+pub(crate) struct Feature {
+    /// The name as used in `target_feature` and `-C target-feature`.
+    pub name: &'static str,
+    /// Whether the feature is enabled at compile time, e.g. via `-C target-cpu=native`.
+    pub enabled: bool,
+    /// How to detect the feature at run time.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    pub bit: arch::Bit,
+}
+
+/// A snapshot of the features supported by the current CPU.
+pub(crate) struct Cpu {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    snapshot: arch::Snapshot,
+}
+
+impl Cpu {
+    /// Query the current CPU.
+    ///
+    /// Note that this can not use `is_x86_feature_detected!` and `is_aarch64_feature_detected!`,
+    /// since those unconditionally return `true` for features enabled at compile time.
+    pub fn read() -> Self {
+        Self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+            snapshot: arch::Snapshot::read(),
+        }
+    }
+
+    /// Whether the CPU supports the given feature.
+    pub fn has(&self, _feature: &Feature) -> bool {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+        return self.snapshot.has(&_feature.bit);
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+        return true;
+    }
+}
+
+/// Build the x86 feature table from `name => (leaf, subleaf) reg[bit] os_support` entries.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+macro_rules! features {
+    ($($name:tt => ($leaf:literal, $subleaf:literal) $reg:ident [$bit:literal] $os:ident,)*) => {
+        &[$(Feature {
+            name: $name,
+            enabled: cfg!(target_feature = $name),
+            bit: arch::Bit {
+                leaf: $leaf,
+                subleaf: $subleaf,
+                reg: arch::Reg::$reg,
+                mask: 1 << $bit,
+                os: arch::OsSupport::$os,
+            },
+        }),*]
+    };
+}
+
+/// Build the aarch64 feature table from `name => hwcap[bits]` entries.
+///
+/// When `hwcap` is not available, this falls back to `is_aarch64_feature_detected!`,
+/// or to the compile-time features without `std`.
+#[cfg(target_arch = "aarch64")]
+macro_rules! features {
+    ($($name:tt => $hwcap:ident [$($bit:literal),*],)*) => {
+        &[$(Feature {
+            name: $name,
+            enabled: cfg!(target_feature = $name),
+            bit: arch::Bit {
+                hwcap: arch::Hwcap::$hwcap,
+                mask: 0 $(| 1 << $bit)*,
+                fallback: fallback!($name),
+            },
+        }),*]
+    };
+}
+
+#[cfg(all(target_arch = "aarch64", feature = "std"))]
+macro_rules! fallback {
+    ($name:tt) => {
+        || std::arch::is_aarch64_feature_detected!($name)
+    };
+}
+#[cfg(all(target_arch = "aarch64", not(feature = "std")))]
+macro_rules! fallback {
+    ($name:tt) => {
+        || cfg!(target_feature = $name)
+    };
+}
+
+/// All x86 features that can be enabled at compile time and detected at run time.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[rustfmt::skip]
+pub(crate) const FEATURES: &[Feature] = features!(
+    // x86-64-v1
+    "sse"                => (0x1, 0) Edx[25] None,
+    "sse2"               => (0x1, 0) Edx[26] None,
+    "fxsr"               => (0x1, 0) Edx[24] None,
+    // x86-64-v2
+    "sse3"               => (0x1, 0) Ecx[0] None,
+    "ssse3"              => (0x1, 0) Ecx[9] None,
+    "sse4.1"             => (0x1, 0) Ecx[19] None,
+    "sse4.2"             => (0x1, 0) Ecx[20] None,
+    "popcnt"             => (0x1, 0) Ecx[23] None,
+    "cmpxchg16b"         => (0x1, 0) Ecx[13] None,
+    // x86-64-v3
+    "avx"                => (0x1, 0) Ecx[28] Avx,
+    "avx2"               => (0x7, 0) Ebx[5] Avx,
+    "bmi1"               => (0x7, 0) Ebx[3] None,
+    "bmi2"               => (0x7, 0) Ebx[8] None,
+    "f16c"               => (0x1, 0) Ecx[29] Avx,
+    "fma"                => (0x1, 0) Ecx[12] Avx,
+    "lzcnt"              => (0x8000_0001, 0) Ecx[5] None,
+    "movbe"              => (0x1, 0) Ecx[22] None,
+    "xsave"              => (0x1, 0) Ecx[26] Xsave,
+    // x86-64-v4
+    "avx512f"            => (0x7, 0) Ebx[16] Avx512,
+    "avx512bw"           => (0x7, 0) Ebx[30] Avx512,
+    "avx512cd"           => (0x7, 0) Ebx[28] Avx512,
+    "avx512dq"           => (0x7, 0) Ebx[17] Avx512,
+    "avx512vl"           => (0x7, 0) Ebx[31] Avx512,
+    // Further AVX-512 extensions.
+    "avx512ifma"         => (0x7, 0) Ebx[21] Avx512,
+    "avx512vbmi"         => (0x7, 0) Ecx[1] Avx512,
+    "avx512vbmi2"        => (0x7, 0) Ecx[6] Avx512,
+    "avx512vnni"         => (0x7, 0) Ecx[11] Avx512,
+    "avx512bitalg"       => (0x7, 0) Ecx[12] Avx512,
+    "avx512vpopcntdq"    => (0x7, 0) Ecx[14] Avx512,
+    "avx512bf16"         => (0x7, 1) Eax[5] Avx512,
+    "avx512fp16"         => (0x7, 0) Edx[23] Avx512,
+    "avx512vp2intersect" => (0x7, 0) Edx[8] Avx512,
+    // Further AVX extensions.
+    "avxvnni"            => (0x7, 1) Eax[4] Avx,
+    "avxifma"            => (0x7, 1) Eax[23] Avx,
+    "avxvnniint8"        => (0x7, 1) Edx[4] Avx,
+    "avxvnniint16"       => (0x7, 1) Edx[10] Avx,
+    "avxneconvert"       => (0x7, 1) Edx[5] Avx,
+    // Miscellaneous.
+    "sse4a"              => (0x8000_0001, 0) Ecx[6] None,
+    "tbm"                => (0x8000_0001, 0) Ecx[21] None,
+    "adx"                => (0x7, 0) Ebx[19] None,
+    "aes"                => (0x1, 0) Ecx[25] None,
+    "pclmulqdq"          => (0x1, 0) Ecx[1] None,
+    "vaes"               => (0x7, 0) Ecx[9] Avx,
+    "vpclmulqdq"         => (0x7, 0) Ecx[10] Avx,
+    "gfni"               => (0x7, 0) Ecx[8] None,
+    "sha"                => (0x7, 0) Ebx[29] None,
+    "sha512"             => (0x7, 1) Eax[0] Avx,
+    "sm3"                => (0x7, 1) Eax[1] Avx,
+    "sm4"                => (0x7, 1) Eax[2] Avx,
+    "rdrand"             => (0x1, 0) Ecx[30] None,
+    "rdseed"             => (0x7, 0) Ebx[18] None,
+    "rtm"                => (0x7, 0) Ebx[11] None,
+    "xsaveopt"           => (0xD, 1) Eax[0] Xsave,
+    "xsavec"             => (0xD, 1) Eax[1] Xsave,
+    "xsaves"             => (0xD, 1) Eax[3] Xsave,
+    "kl"                 => (0x19, 0) Ebx[0] None,
+    "widekl"             => (0x19, 0) Ebx[2] None,
+);
+
+/// All aarch64 features that can be enabled at compile time and detected at run time.
+///
+/// Some target features correspond to multiple `hwcap` bits.
+#[cfg(target_arch = "aarch64")]
+#[rustfmt::skip]
+pub(crate) const FEATURES: &[Feature] = features!(
+    "neon"         => Hwcap[0, 1],
+    "sve"          => Hwcap[22],
+    "sve2"         => Hwcap2[1],
+    "sve2-aes"     => Hwcap2[2, 3],
+    "sve2-bitperm" => Hwcap2[4],
+    "sve2-sha3"    => Hwcap2[5],
+    "sve2-sm4"     => Hwcap2[6],
+    "f32mm"        => Hwcap2[10],
+    "f64mm"        => Hwcap2[11],
+    "aes"          => Hwcap[3, 4],
+    "sha2"         => Hwcap[5, 6],
+    "sha3"         => Hwcap[17, 21],
+    "sm4"          => Hwcap[18, 19],
+    "crc"          => Hwcap[7],
+    "lse"          => Hwcap[8],
+    "lse2"         => Hwcap[25],
+    "fp16"         => Hwcap[9, 10],
+    "rdm"          => Hwcap[12],
+    "jsconv"       => Hwcap[13],
+    "fcma"         => Hwcap[14],
+    "rcpc"         => Hwcap[15],
+    "rcpc2"        => Hwcap[26],
+    "dpb"          => Hwcap[16],
+    "dpb2"         => Hwcap2[0],
+    "dotprod"      => Hwcap[20],
+    "fhm"          => Hwcap[23],
+    "dit"          => Hwcap[24],
+    "flagm"        => Hwcap[27],
+    "ssbs"         => Hwcap[28],
+    "sb"           => Hwcap[29],
+    "paca"         => Hwcap[30],
+    "pacg"         => Hwcap[31],
+    "frintts"      => Hwcap2[8],
+    "i8mm"         => Hwcap2[13],
+    "bf16"         => Hwcap2[14],
+    "rand"         => Hwcap2[16],
+    "bti"          => Hwcap2[17],
+    "mte"          => Hwcap2[18],
+);
+
+/// On other architectures, no run-time checks are done.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+pub(crate) const FEATURES: &[Feature] = &[];
+
+/// The `cpuid` masks of the features enabled at compile time.
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+const COMPILED_MASKS: arch::Masks = {
+    let mut masks = arch::Masks::EMPTY;
+    let mut i = 0;
+    while i < FEATURES.len() {
+        if FEATURES[i].enabled {
+            masks = masks.with(&FEATURES[i].bit);
+        }
+        i += 1;
+    }
+    masks
+};
+
+/// Whether the CPU supports all features enabled at compile time.
+///
+/// Unlike [`FeatureSet::detected`], this is safe to run on a CPU that lacks them: it only compares
+/// the raw `cpuid` and `xcr0` words against masks computed at compile time, and uses no loops or
+/// shifts that LLVM could compile to instructions of the enabled features.
+/// On other architectures, this always returns `true`, and the full check is relied upon.
+#[cfg(feature = "std")]
+#[inline(never)]
+pub(crate) fn compiled_supported() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    return COMPILED_MASKS.supported();
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    return true;
+}
+
+const _: () = assert!(
+    FEATURES.len() <= 128,
+    "FeatureSet only has room for 128 features"
+);
+
+/// A set of target features, e.g. those enabled at compile time or detected at run time.
+///
+/// Only contains features known to this crate.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FeatureSet(u128);
+
+impl FeatureSet {
+    /// The empty set.
+    pub const EMPTY: Self = Self(0);
+
+    /// All features known on the current architecture.
+    pub const ALL: Self = Self(if FEATURES.len() == 128 {
+        u128::MAX
+    } else {
+        (1 << FEATURES.len()) - 1
+    });
+
+    /// The features enabled at compile time.
+    pub const fn compiled() -> Self {
+        let mut set = 0;
+        let mut i = 0;
+        while i < FEATURES.len() {
+            if FEATURES[i].enabled {
+                set |= 1 << i;
+            }
+            i += 1;
+        }
+        Self(set)
+    }
+
+    /// The set of the given features.
+    ///
+    /// Panics (at compile time, when used in a `const`) when a feature is not known on the current architecture.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    pub(crate) const fn from_names(names: &[&str]) -> Self {
+        let mut set = 0;
+        let mut j = 0;
+        while j < names.len() {
+            let mut i = 0;
+            while !str_eq(FEATURES[i].name, names[j]) {
+                i += 1;
+            }
+            set |= 1 << i;
+            j += 1;
+        }
+        Self(set)
+    }
+
+    /// The set of the given features, ignoring those that are not known on the current architecture.
+    pub const fn from_known_names(names: &[&str]) -> Self {
+        let mut set = 0;
+        let mut j = 0;
+        while j < names.len() {
+            let mut i = 0;
+            while i < FEATURES.len() {
+                if str_eq(FEATURES[i].name, names[j]) {
+                    set |= 1 << i;
+                }
+                i += 1;
+            }
+            j += 1;
+        }
+        Self(set)
+    }
+
+    /// The features supported by the current CPU.
+    ///
+    /// Inside [`with_detector`](crate::with_detector), these are the features of the given detector instead.
+    /// Otherwise, features can be hidden using the `ENSURE_SIMD_LEVEL` and `ENSURE_SIMD_DISABLE`
+    /// environment variables.
+    pub fn detected() -> Self {
+        #[cfg(feature = "std")]
+        if let Some(set) = crate::detector::thread_override() {
+            return set;
+        }
+        let set = Self::from_cpu(&Cpu::read());
+        #[cfg(feature = "std")]
+        let set = set.intersection(&crate::env::allowed());
+        set
+    }
+
+    pub(crate) fn from_cpu(cpu: &Cpu) -> Self {
+        let mut set = 0;
+        // Shift a single bit along, rather than computing `1 << i`, since variable shifts can
+        // compile to `shlx` when BMI2 is enabled, and this must run on CPUs without it.
+        let mut bit = 1;
+        for f in FEATURES {
+            if cpu.has(f) {
+                set |= bit;
+            }
+            bit <<= 1;
+        }
+        Self(set)
+    }
+
+    pub(crate) const fn bits(&self) -> u128 {
+        self.0
+    }
+
+    /// Whether the feature with the given name is in the set.
+    pub fn contains(&self, name: &str) -> bool {
+        self.iter().any(|f| f == name)
+    }
+
+    /// Whether all features in `other` are also in `self`.
+    pub const fn is_superset(&self, other: &Self) -> bool {
+        other.0 & !self.0 == 0
+    }
+
+    /// The features in `self` or in `other`.
+    pub const fn union(&self, other: &Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// The features in both `self` and `other`.
+    pub const fn intersection(&self, other: &Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// The features in `self` that are not in `other`.
+    pub const fn difference(&self, other: &Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// Whether the set contains no features.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The number of features in the set.
+    pub const fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Iterate over the feature names in the set.
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> + '_ {
+        FEATURES
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.0 & (1 << i) != 0)
+            .map(|(_, f)| f.name)
+    }
+}
+
+impl core::fmt::Debug for FeatureSet {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Comma-separated list of feature names.
+impl core::fmt::Display for FeatureSet {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, name) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// `const` string equality.
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
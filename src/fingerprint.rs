@@ -0,0 +1,122 @@
+//! Fingerprints of the build CPU and the host CPU, for `-C target-cpu=native` builds.
+
+use crate::FeatureSet;
+
+/// The vendor, model, and features of a CPU.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CpuFingerprint {
+    /// E.g. `GenuineIntel` or `AuthenticAMD` on x86, or the `CPU implementer` on aarch64.
+    pub vendor: String,
+    /// The processor brand string on x86, or the `CPU part` on aarch64.
+    pub model: String,
+    /// The known features of the CPU.
+    pub features: FeatureSet,
+}
+
+/// The result of [`CpuFingerprint::compare`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FingerprintMatch {
+    /// The host is the same kind of CPU as the build machine.
+    Same,
+    /// The host supports all features of the build machine, but is a different CPU.
+    /// The binary runs, but may not be optimal for this CPU.
+    Differs,
+    /// The host lacks features of the build machine, so the binary will likely crash.
+    Incompatible,
+}
+
+impl CpuFingerprint {
+    /// The CPU this binary was built on, when it was built with `-C target-cpu=native`.
+    pub fn build() -> Option<Self> {
+        #[cfg(ensure_simd_native)]
+        return Some(Self {
+            vendor: env!("ENSURE_SIMD_BUILD_CPU_VENDOR").to_string(),
+            model: env!("ENSURE_SIMD_BUILD_CPU_MODEL").to_string(),
+            features: FeatureSet::compiled(),
+        });
+        #[cfg(not(ensure_simd_native))]
+        return None;
+    }
+
+    /// The full list of target features enabled for the build CPU, including those unknown to this crate.
+    pub fn build_target_features() -> Option<&'static str> {
+        #[cfg(ensure_simd_native)]
+        return Some(env!("ENSURE_SIMD_BUILD_TARGET_FEATURES"));
+        #[cfg(not(ensure_simd_native))]
+        return None;
+    }
+
+    /// The current CPU.
+    pub fn host() -> Self {
+        let (vendor, model) = host_model();
+        Self {
+            vendor,
+            model,
+            features: FeatureSet::detected(),
+        }
+    }
+
+    /// Compare the build CPU `self` to the `host` CPU.
+    pub fn compare(&self, host: &Self) -> FingerprintMatch {
+        if !host.features.is_superset(&self.features) {
+            FingerprintMatch::Incompatible
+        } else if self != host {
+            FingerprintMatch::Differs
+        } else {
+            FingerprintMatch::Same
+        }
+    }
+}
+
+impl core::fmt::Display for CpuFingerprint {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} ({})", self.model, self.vendor)
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn host_model() -> (String, String) {
+    let string = |bytes: &[u8]| {
+        String::from_utf8_lossy(bytes)
+            .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+            .to_string()
+    };
+    (
+        string(&crate::cpuid::vendor()),
+        string(&crate::cpuid::brand()),
+    )
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn host_model() -> (String, String) {
+    // Same as the build script: the first matching lines of `/proc/cpuinfo`.
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let field = |name: &str| {
+        cpuinfo
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+            .unwrap_or_default()
+    };
+    (field("CPU implementer"), field("CPU part"))
+}
+
+/// A warning when this `-C target-cpu=native` binary runs on a different, but compatible, CPU.
+pub(crate) fn mismatch_warning() -> Option<String> {
+    let build = CpuFingerprint::build()?;
+    let host = CpuFingerprint::host();
+    if build.compare(&host) != FingerprintMatch::Differs {
+        return None;
+    }
+    let mut msg = format!(
+        "Warning: this binary was built with `-C target-cpu=native` for a different CPU: {build}.
+It runs on this CPU ({host}), but may not use all its features"
+    );
+    let extra = host.features.difference(&build.features);
+    if !extra.is_empty() {
+        msg += &format!(" ({extra})");
+    }
+    msg += ".\nRebuild on this machine for the best performance.";
+    Some(msg)
+}
@@ -0,0 +1,46 @@
+//! A hint for binaries built without SIMD (e.g. with the `scalar` feature) that run on a CPU supporting it.
+
+use std::sync::Once;
+
+use crate::{FeatureSet, SimdLevel};
+
+/// Set this environment variable to suppress the hint printed by [`ensure_simd`](crate::ensure_simd).
+const NO_HINT: &str = "ENSURE_SIMD_NO_HINT";
+
+/// A hint to reinstall the binary, when it was built without the target features of the
+/// [required](SimdLevel::required) SIMD level, or AVX2 by default (e.g. using the `scalar` feature,
+/// or in a debug build), but the current CPU supports them.
+///
+/// Returns `None` when the binary already uses these features, or the CPU does not support them.
+/// Tools can show this in e.g. their `--version` output.
+/// With the `hint` feature, [`ensure_simd`](crate::ensure_simd) prints it once.
+pub fn upgrade_hint() -> Option<String> {
+    let required = SimdLevel::required_features();
+    if FeatureSet::compiled().is_superset(&required)
+        || !FeatureSet::detected().is_superset(&required)
+    {
+        return None;
+    }
+    let missing = required.difference(&FeatureSet::compiled());
+    let compiled = SimdLevel::compiled();
+    let detected = SimdLevel::detected();
+    Some(format!(
+        "Hint: this binary was built for {compiled}, but your CPU supports {detected}.
+It is missing the SIMD fast path using {missing}, and may be 2-4x slower than it could be.
+For better performance, reinstall using:
+RUSTFLAGS=\"-C target-cpu=native\" cargo install ..."
+    ))
+}
+
+/// Print the [`upgrade_hint`] to stderr, at most once per process, unless `ENSURE_SIMD_NO_HINT` is set.
+pub(crate) fn print_upgrade_hint() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        if std::env::var_os(NO_HINT).is_some() {
+            return;
+        }
+        if let Some(hint) = upgrade_hint() {
+            eprintln!("\n{hint}\nSet {NO_HINT}=1 to hide this hint.\n");
+        }
+    });
+}
@@ -0,0 +1,55 @@
+//! Run-time feature detection on aarch64 using the `AT_HWCAP` and `AT_HWCAP2` auxiliary vector entries.
+//!
+//! On operating systems without `getauxval`, this falls back to `is_aarch64_feature_detected!`,
+//! which assumes that features enabled at compile time are available, or, without `std`,
+//! directly to the features enabled at compile time.
+
+/// The auxiliary vector entry containing a feature bit.
+#[derive(Clone, Copy)]
+pub(crate) enum Hwcap {
+    Hwcap,
+    Hwcap2,
+}
+
+/// The location of a feature in the `hwcap` bits.
+#[derive(Clone, Copy)]
+pub(crate) struct Bit {
+    pub hwcap: Hwcap,
+    /// All of these bits must be set.
+    pub mask: u64,
+    /// Detection when `hwcap` is not available.
+    pub fallback: fn() -> bool,
+}
+
+/// The `hwcap` values of the current process, if available.
+pub(crate) struct Snapshot {
+    hwcap: Option<[u64; 2]>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe extern "C" {
+    fn getauxval(ty: core::ffi::c_ulong) -> core::ffi::c_ulong;
+}
+
+impl Snapshot {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn read() -> Self {
+        const AT_HWCAP: core::ffi::c_ulong = 16;
+        const AT_HWCAP2: core::ffi::c_ulong = 26;
+        // SAFETY: `getauxval` is always safe to call, and returns 0 for unknown entries.
+        let hwcap = unsafe { [getauxval(AT_HWCAP) as u64, getauxval(AT_HWCAP2) as u64] };
+        Self { hwcap: Some(hwcap) }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn read() -> Self {
+        Self { hwcap: None }
+    }
+
+    pub fn has(&self, bit: &Bit) -> bool {
+        match self.hwcap {
+            Some(hwcap) => hwcap[bit.hwcap as usize] & bit.mask == bit.mask,
+            None => (bit.fallback)(),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Reading the `.note.ensure_simd` build metadata of other binaries, without running them.
+
+use crate::elf::Elf;
+use crate::{FeatureSet, NOTE_TYPE, SimdLevel};
+use std::path::Path;
+
+/// The build metadata of a binary linking `ensure_simd`, as returned by [`inspect`].
+///
+/// See [`build_note`](crate::build_note) for the metadata of the current binary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BinaryInfo {
+    /// The target triple, e.g. `x86_64-unknown-linux-gnu`.
+    pub target: String,
+    /// The SIMD level the binary was compiled for, and thus requires.
+    pub level: SimdLevel,
+    /// Whether the binary was built with the `scalar` feature.
+    pub scalar: bool,
+    /// The `rustc --version` used for the build.
+    pub rustc: String,
+    /// All target features enabled at compile time, including those unknown to this crate.
+    pub target_features: Vec<String>,
+}
+
+/// Whether a binary can run on a CPU. See [`BinaryInfo::check_host`] and [`BinaryInfo::check_target_cpu`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Compatibility {
+    /// The CPU supports all target features of the binary.
+    Runs,
+    /// The CPU lacks these target features of the binary, so it will likely crash.
+    Missing(Vec<String>),
+    /// The binary was built for another architecture than that of the CPU.
+    OtherArch {
+        /// The architecture of the binary.
+        binary: String,
+        /// The architecture of the CPU.
+        cpu: String,
+    },
+}
+
+impl Compatibility {
+    /// Whether the binary runs.
+    pub fn runs(&self) -> bool {
+        *self == Compatibility::Runs
+    }
+}
+
+/// Error returned by [`inspect`], [`scan`](crate::scan), and [`BinaryInfo::check_target_cpu`].
+#[derive(Debug)]
+pub enum InspectError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The file is not an ELF file.
+    NotElf,
+    /// The file has no `.note.ensure_simd` note, e.g. because it does not link `ensure_simd`.
+    NoNote,
+    /// The note is malformed, or was written by an incompatible version of `ensure_simd`.
+    InvalidNote(String),
+    /// The binary is for an unsupported architecture, with the given ELF `e_machine`.
+    UnsupportedMachine(u16),
+    /// The features of the target CPU could not be determined using `rustc`.
+    TargetCpu(String),
+}
+
+impl core::fmt::Display for InspectError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InspectError::Io(err) => write!(f, "could not read binary: {err}"),
+            InspectError::NotElf => f.write_str("not an ELF file"),
+            InspectError::NoNote => f.write_str(
+                "no `.note.ensure_simd` note found; the binary does not link a recent `ensure_simd`",
+            ),
+            InspectError::InvalidNote(reason) => write!(f, "invalid `.note.ensure_simd` note: {reason}"),
+            InspectError::UnsupportedMachine(machine) => {
+                write!(f, "unsupported architecture (ELF machine {machine})")
+            }
+            InspectError::TargetCpu(reason) => write!(f, "unknown target CPU: {reason}"),
+        }
+    }
+}
+
+impl core::error::Error for InspectError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            InspectError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for InspectError {
+    fn from(err: std::io::Error) -> Self {
+        InspectError::Io(err)
+    }
+}
+
+/// Read the build metadata embedded in the ELF binary or shared library at `path`.
+///
+/// ```no_run
+/// let info = ensure_simd::inspect("target/release/tool")?;
+/// println!("requires {}", info.level);
+/// if !info.check_target_cpu("x86-64-v3")?.runs() {
+///     eprintln!("not portable");
+/// }
+/// # Ok::<(), ensure_simd::InspectError>(())
+/// ```
+pub fn inspect(path: impl AsRef<Path>) -> Result<BinaryInfo, InspectError> {
+    inspect_bytes(&std::fs::read(path)?)
+}
+
+/// Like [`inspect`], for the contents of a binary.
+pub fn inspect_bytes(data: &[u8]) -> Result<BinaryInfo, InspectError> {
+    let elf = Elf::parse(data).ok_or(InspectError::NotElf)?;
+    let notes = elf.notes();
+    let (_, ty, desc) = notes
+        .iter()
+        .find(|(owner, _, _)| *owner == "ensure_simd")
+        .ok_or(InspectError::NoNote)?;
+    if *ty != NOTE_TYPE {
+        return Err(InspectError::InvalidNote(format!(
+            "unsupported note type {ty}"
+        )));
+    }
+    let text = core::str::from_utf8(desc)
+        .map_err(|_| InspectError::InvalidNote("not utf-8".to_string()))?;
+    BinaryInfo::parse(text)
+}
+
+impl BinaryInfo {
+    /// Parse the text of a note, as returned by [`build_note`](crate::build_note).
+    pub fn parse(note: &str) -> Result<Self, InspectError> {
+        let value = |key: &str| {
+            note.lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                .ok_or_else(|| InspectError::InvalidNote(format!("missing `{key}`")))
+        };
+        if value("version")? != "1" {
+            return Err(InspectError::InvalidNote(format!(
+                "unsupported version {}",
+                value("version")?
+            )));
+        }
+        let level = value("level")?;
+        Ok(BinaryInfo {
+            target: value("target")?.to_string(),
+            level: level
+                .parse()
+                .map_err(|_| InspectError::InvalidNote(format!("unknown level `{level}`")))?,
+            scalar: value("scalar")? == "true",
+            rustc: value("rustc")?.to_string(),
+            target_features: value("features")?
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+
+    /// The architecture of the binary, as in `cfg(target_arch)`, e.g. `x86_64` or `aarch64`.
+    pub fn arch(&self) -> &str {
+        let arch = self.target.split('-').next().unwrap_or_default();
+        match arch {
+            "i386" | "i586" | "i686" => "x86",
+            "arm64" => "aarch64",
+            _ if arch.starts_with("armv") || arch.starts_with("thumbv") => "arm",
+            _ if arch.starts_with("riscv64") => "riscv64",
+            _ if arch.starts_with("riscv32") => "riscv32",
+            _ => arch,
+        }
+    }
+
+    /// The target features of the binary that are not in `supported`.
+    ///
+    /// Features that do not describe the CPU, such as `crt-static`, are ignored.
+    pub fn missing_features(&self, supported: &[&str]) -> Vec<String> {
+        self.target_features
+            .iter()
+            .filter(|f| *f != "crt-static" && !supported.contains(&f.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether the binary can run on the current CPU.
+    ///
+    /// Like [`check_simd`](crate::check_simd), this only checks the features known to this crate,
+    /// and respects the `ENSURE_SIMD_LEVEL` and `ENSURE_SIMD_DISABLE` environment variables.
+    pub fn check_host(&self) -> Compatibility {
+        if self.arch() != std::env::consts::ARCH {
+            return Compatibility::OtherArch {
+                binary: self.arch().to_string(),
+                cpu: std::env::consts::ARCH.to_string(),
+            };
+        }
+        let names: Vec<&str> = self.target_features.iter().map(String::as_str).collect();
+        let missing = FeatureSet::from_known_names(&names).difference(&FeatureSet::detected());
+        if missing.is_empty() {
+            Compatibility::Runs
+        } else {
+            Compatibility::Missing(missing.iter().map(str::to_string).collect())
+        }
+    }
+
+    /// Whether the binary can run on the given `-C target-cpu`, e.g. `x86-64-v3`, `znver3`, or `apple-m1`.
+    ///
+    /// The features of the CPU are taken from `rustc --print cfg` for the target of the binary,
+    /// using `$RUSTC` or `rustc` from the `PATH`.
+    pub fn check_target_cpu(&self, cpu: &str) -> Result<Compatibility, InspectError> {
+        let features = target_cpu_features(&self.target, cpu)?;
+        let features: Vec<&str> = features.iter().map(String::as_str).collect();
+        let missing = self.missing_features(&features);
+        Ok(if missing.is_empty() {
+            Compatibility::Runs
+        } else {
+            Compatibility::Missing(missing)
+        })
+    }
+}
+
+/// The target features enabled by `-C target-cpu={cpu}` for `target`, according to `rustc`.
+fn target_cpu_features(target: &str, cpu: &str) -> Result<Vec<String>, InspectError> {
+    let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = std::process::Command::new(&rustc)
+        .args(["--print", "cfg", "--target", target])
+        .arg(format!("-Ctarget-cpu={cpu}"))
+        .output()
+        .map_err(|err| {
+            InspectError::TargetCpu(format!("could not run `{}`: {err}", rustc.display()))
+        })?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // `rustc` only warns about unknown CPUs, and then falls back to the generic one.
+    if !output.status.success() || stderr.contains("is not a recognized processor") {
+        let reason = stderr.lines().next().unwrap_or_default();
+        return Err(InspectError::TargetCpu(format!(
+            "`{cpu}` for `{target}`: {reason}"
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix("target_feature=\"")?.strip_suffix('"'))
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::tests::{build, note};
+    use crate::elf::{EM_X86_64, SHT_NOTE};
+
+    const NOTE: &str = "version=1
+target=x86_64-unknown-linux-gnu
+level=x86-64-v3
+scalar=false
+rustc=rustc 1.95.0 (59807616e 2026-04-14)
+features=avx,avx2,crt-static,sse,sse2
+";
+
+    fn binary(notes: &[u8]) -> Vec<u8> {
+        build(EM_X86_64, &[(".note.ensure_simd", SHT_NOTE, 0, 0, notes)])
+    }
+
+    #[test]
+    fn from_note() {
+        let mut notes = note("GNU", 3, &[0; 20]);
+        notes.extend(note("ensure_simd", NOTE_TYPE, NOTE.as_bytes()));
+        let info = inspect_bytes(&binary(&notes)).unwrap();
+        assert_eq!(
+            info,
+            BinaryInfo {
+                target: "x86_64-unknown-linux-gnu".to_string(),
+                level: SimdLevel::X86_64V3,
+                scalar: false,
+                rustc: "rustc 1.95.0 (59807616e 2026-04-14)".to_string(),
+                target_features: ["avx", "avx2", "crt-static", "sse", "sse2"]
+                    .map(String::from)
+                    .to_vec(),
+            }
+        );
+        assert_eq!(info.arch(), "x86_64");
+        assert_eq!(info.missing_features(&["sse", "sse2", "avx"]), ["avx2"]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(inspect_bytes(b"MZ"), Err(InspectError::NotElf)));
+        assert!(matches!(
+            inspect_bytes(&binary(&note("GNU", NOTE_TYPE, NOTE.as_bytes()))),
+            Err(InspectError::NoNote)
+        ));
+        assert!(matches!(
+            inspect_bytes(&binary(&note("ensure_simd", 2, NOTE.as_bytes()))),
+            Err(InspectError::InvalidNote(_))
+        ));
+        let version2 = NOTE.replace("version=1", "version=2");
+        assert!(matches!(
+            inspect_bytes(&binary(&note(
+                "ensure_simd",
+                NOTE_TYPE,
+                version2.as_bytes()
+            ))),
+            Err(InspectError::InvalidNote(_))
+        ));
+        assert!(matches!(
+            BinaryInfo::parse("version=1\nlevel=v3\n"),
+            Err(InspectError::InvalidNote(reason)) if reason == "missing `target`"
+        ));
+    }
+
+    /// The note of the current binary, as embedded by this crate.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn current_exe() {
+        let info = inspect(std::env::current_exe().unwrap()).unwrap();
+        assert_eq!(info, BinaryInfo::parse(crate::build_note()).unwrap());
+        assert_eq!(info.level, SimdLevel::compiled());
+        assert_eq!(info.arch(), std::env::consts::ARCH);
+        assert_eq!(info.check_host(), Compatibility::Runs);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::FeatureSet;
+
+/// SIMD capability levels: the x86-64 microarchitecture levels and the aarch64 NEON/SVE tiers.
+///
+/// Levels are ordered from least to most capable within each architecture.
+/// Comparing levels of different architectures is not meaningful.
+///
+/// See <https://en.wikipedia.org/wiki/X86-64#Microarchitecture_levels>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SimdLevel {
+    /// No known SIMD level.
+    Scalar,
+    /// SSE and SSE2. The x86-64 baseline.
+    X86_64V1,
+    /// Adds SSE3, SSSE3, SSE4.1, SSE4.2, and POPCNT.
+    X86_64V2,
+    /// Adds AVX, AVX2, BMI1, BMI2, F16C, FMA, LZCNT, and MOVBE.
+    X86_64V3,
+    /// Adds AVX-512 F, BW, CD, DQ, and VL.
+    X86_64V4,
+    /// 128-bit NEON. The aarch64 baseline.
+    Neon,
+    /// Scalable vectors.
+    Sve,
+    /// Scalable vectors, version 2.
+    Sve2,
+}
+
+/// The levels of the current architecture, together with the features they require.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const LEVELS: &[(SimdLevel, FeatureSet)] = &[
+    level(SimdLevel::X86_64V1),
+    level(SimdLevel::X86_64V2),
+    level(SimdLevel::X86_64V3),
+    level(SimdLevel::X86_64V4),
+];
+#[cfg(target_arch = "aarch64")]
+const LEVELS: &[(SimdLevel, FeatureSet)] = &[
+    level(SimdLevel::Neon),
+    level(SimdLevel::Sve),
+    level(SimdLevel::Sve2),
+];
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+const LEVELS: &[(SimdLevel, FeatureSet)] = &[];
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+const fn level(level: SimdLevel) -> (SimdLevel, FeatureSet) {
+    (level, FeatureSet::from_names(level.target_features()))
+}
+
+impl SimdLevel {
+    /// All levels, in increasing order.
+    pub const ALL: [SimdLevel; 8] = [
+        SimdLevel::Scalar,
+        SimdLevel::X86_64V1,
+        SimdLevel::X86_64V2,
+        SimdLevel::X86_64V3,
+        SimdLevel::X86_64V4,
+        SimdLevel::Neon,
+        SimdLevel::Sve,
+        SimdLevel::Sve2,
+    ];
+
+    /// The level the binary was compiled for.
+    pub const fn compiled() -> Self {
+        Self::best_for(FeatureSet::compiled())
+    }
+
+    /// The level supported by the current CPU.
+    pub fn detected() -> Self {
+        Self::best_for(FeatureSet::detected())
+    }
+
+    /// The minimum level required by the `require-*` features of this crate.
+    ///
+    /// Without any `require-*` feature, this is `x86-64` (v1) on x86 and `neon` on aarch64.
+    /// The compile-time check then still requires AVX2 on x86.
+    /// On other architectures, this is [`SimdLevel::Scalar`].
+    pub const fn required() -> Self {
+        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+            if cfg!(feature = "require-v4") {
+                SimdLevel::X86_64V4
+            } else if cfg!(feature = "require-v3") {
+                SimdLevel::X86_64V3
+            } else if cfg!(feature = "require-v2") {
+                SimdLevel::X86_64V2
+            } else {
+                SimdLevel::X86_64V1
+            }
+        } else if cfg!(target_arch = "aarch64") {
+            if cfg!(feature = "require-sve2") {
+                SimdLevel::Sve2
+            } else if cfg!(feature = "require-sve") {
+                SimdLevel::Sve
+            } else {
+                SimdLevel::Neon
+            }
+        } else {
+            SimdLevel::Scalar
+        }
+    }
+
+    /// The target features required by the compile-time check: those of [`SimdLevel::required`],
+    /// and, without any `require-*` feature, AVX2 on x86, as before these features existed.
+    pub(crate) const fn required_features() -> FeatureSet {
+        let features = Self::required().feature_set();
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if !cfg!(feature = "require-v2") {
+            return features.union(&FeatureSet::from_names(&["avx2"]));
+        }
+        features
+    }
+
+    /// Whether this compiled level is sufficient for the `required` level.
+    ///
+    /// [`SimdLevel::Scalar`] never is, since that means that no SIMD fast path is known.
+    pub(crate) const fn meets(self, required: Self) -> bool {
+        !matches!(self, SimdLevel::Scalar) && self as u8 >= required as u8
+    }
+
+    /// The features of this level that are known on the current architecture.
+    pub(crate) const fn feature_set(self) -> FeatureSet {
+        let mut i = 0;
+        while i < LEVELS.len() {
+            if LEVELS[i].0 as u8 == self as u8 {
+                return LEVELS[i].1;
+            }
+            i += 1;
+        }
+        FeatureSet::EMPTY
+    }
+
+    /// The highest level of the current architecture for which all features are in the given set.
+    pub const fn best_for(features: FeatureSet) -> Self {
+        let mut best = SimdLevel::Scalar;
+        let mut i = 0;
+        while i < LEVELS.len() {
+            if features.is_superset(&LEVELS[i].1) {
+                best = LEVELS[i].0;
+            }
+            i += 1;
+        }
+        best
+    }
+
+    /// The name of the level, as used for `-C target-cpu` on x86-64.
+    pub const fn name(self) -> &'static str {
+        match self {
+            SimdLevel::Scalar => "scalar",
+            SimdLevel::X86_64V1 => "x86-64",
+            SimdLevel::X86_64V2 => "x86-64-v2",
+            SimdLevel::X86_64V3 => "x86-64-v3",
+            SimdLevel::X86_64V4 => "x86-64-v4",
+            SimdLevel::Neon => "neon",
+            SimdLevel::Sve => "sve",
+            SimdLevel::Sve2 => "sve2",
+        }
+    }
+
+    /// All target features required by this level, including those of lower levels.
+    pub const fn target_features(self) -> &'static [&'static str] {
+        match self {
+            SimdLevel::Scalar => &[],
+            SimdLevel::X86_64V1 => crate::__simd_level!(__simd_cfg @features v1),
+            SimdLevel::X86_64V2 => crate::__simd_level!(__simd_cfg @features v2),
+            SimdLevel::X86_64V3 => crate::__simd_level!(__simd_cfg @features v3),
+            SimdLevel::X86_64V4 => crate::__simd_level!(__simd_cfg @features v4),
+            SimdLevel::Neon => crate::__simd_level!(__simd_cfg @features neon),
+            SimdLevel::Sve => crate::__simd_level!(__simd_cfg @features sve),
+            SimdLevel::Sve2 => crate::__simd_level!(__simd_cfg @features sve2),
+        }
+    }
+}
+
+impl core::fmt::Display for SimdLevel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Error returned when parsing an unknown [`SimdLevel`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseSimdLevelError(());
+
+impl core::fmt::Display for ParseSimdLevelError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(
+            "unknown SIMD level; expected one of `scalar`, `v1`, `v2`, `v3`, `v4`, `neon`, `sve`, `sve2`",
+        )
+    }
+}
+
+impl core::error::Error for ParseSimdLevelError {}
+
+impl core::str::FromStr for SimdLevel {
+    type Err = ParseSimdLevelError;
+
+    /// Parse a level [name](SimdLevel::name), or `v1` to `v4` for the x86-64 levels.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "scalar" => SimdLevel::Scalar,
+            "v1" | "x86-64" | "x86-64-v1" => SimdLevel::X86_64V1,
+            "v2" | "x86-64-v2" => SimdLevel::X86_64V2,
+            "v3" | "x86-64-v3" => SimdLevel::X86_64V3,
+            "v4" | "x86-64-v4" => SimdLevel::X86_64V4,
+            "neon" => SimdLevel::Neon,
+            "sve" => SimdLevel::Sve,
+            "sve2" => SimdLevel::Sve2,
+            _ => return Err(ParseSimdLevelError(())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str() {
+        assert_eq!("scalar".parse(), Ok(SimdLevel::Scalar));
+        assert_eq!("v1".parse(), Ok(SimdLevel::X86_64V1));
+        assert_eq!("x86-64".parse(), Ok(SimdLevel::X86_64V1));
+        assert_eq!("v2".parse(), Ok(SimdLevel::X86_64V2));
+        assert_eq!("x86-64-v3".parse(), Ok(SimdLevel::X86_64V3));
+        assert_eq!("v4".parse(), Ok(SimdLevel::X86_64V4));
+        assert_eq!("sve2".parse(), Ok(SimdLevel::Sve2));
+        assert!("V3".parse::<SimdLevel>().is_err());
+        assert!("avx2".parse::<SimdLevel>().is_err());
+        assert!("".parse::<SimdLevel>().is_err());
+    }
+
+    #[test]
+    fn name_round_trips() {
+        for level in SimdLevel::ALL {
+            assert_eq!(level.name().parse(), Ok(level));
+        }
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn best_for() {
+        use crate::MockCpu;
+        assert_eq!(SimdLevel::best_for(FeatureSet::EMPTY), SimdLevel::Scalar);
+        assert_eq!(
+            SimdLevel::best_for(MockCpu::NEHALEM.features),
+            SimdLevel::X86_64V2
+        );
+        assert_eq!(
+            SimdLevel::best_for(MockCpu::HASWELL.features),
+            SimdLevel::X86_64V3
+        );
+        assert_eq!(SimdLevel::best_for(FeatureSet::ALL), SimdLevel::X86_64V4);
+        for level in [
+            SimdLevel::X86_64V1,
+            SimdLevel::X86_64V2,
+            SimdLevel::X86_64V3,
+            SimdLevel::X86_64V4,
+        ] {
+            assert_eq!(SimdLevel::best_for(level.feature_set()), level);
+        }
+        // A missing feature drops to the level below.
+        let no_fma = SimdLevel::X86_64V3
+            .feature_set()
+            .difference(&FeatureSet::from_names(&["fma"]));
+        assert_eq!(SimdLevel::best_for(no_fma), SimdLevel::X86_64V2);
+    }
+
+    #[test]
+    #[cfg(target_arch = "aarch64")]
+    fn best_for() {
+        use crate::MockCpu;
+        assert_eq!(SimdLevel::best_for(FeatureSet::EMPTY), SimdLevel::Scalar);
+        assert_eq!(
+            SimdLevel::best_for(MockCpu::GRAVITON2.features),
+            SimdLevel::Neon
+        );
+        assert_eq!(
+            SimdLevel::best_for(MockCpu::GRAVITON3.features),
+            SimdLevel::Sve
+        );
+    }
+}
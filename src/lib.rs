@@ -1,6 +1,9 @@
 //! This library does a compile-time check that the target architecture supports either 128-bit
 //! NEON instructions (always available on aarch64), or 256-bit AVX2 instructions (must be explicitly enabled on x86-64).
 //!
+//! Instead, a minimum level can be required using the `require-v2`, `require-v3`, and `require-v4` features
+//! on x86-64, and the `require-sve` and `require-sve2` features on aarch64. See [`SimdLevel::required`].
+//!
 //! In case the AVX2 feature is not enabled on x64, crates like `wide` and the
 //! `portable-simd` feature will automatically fall back to scalar or 128-bit SIMD
 //! instructions, which are less efficient than the intended 256-bit AVX2 instructions.
@@ -12,44 +15,291 @@
 //!
 //! The [`ensure_simd`] function can be used at the start of `main()` to do a
 //! run-time check that the CPU that is running the binary actually supports
-//! AVX2 instructions.
+//! AVX2 instructions, and all other target features enabled at compile time.
+//!
+//! Without the default `std` feature, this crate is `no_std`. Use [`check_simd`] or
+//! [`check_simd_report`] instead, which detect features using `cpuid` directly and leave the
+//! decision what to do on failure to the caller.
 //!
 //! See the github readme for more details:
 //! <https://github.com/ragnargrootkoerkamp/ensure_simd>.
 
-#[cfg(not(any(
-    doc,
-    debug_assertions,
-    target_feature = "avx2",
-    target_feature = "neon",
-    feature = "scalar"
-)))]
-compile_error!("
-The tool you are trying to build uses AVX2 (on x64) or NEON (on aarch64) SIMD instructions for performance.
-Unfortunately, AVX2 is not enabled by default on x64.
-To get the expected performance, compile/install using e.g.:
-RUSTFLAGS=\"-C target-cpu=native\" cargo ...
-Alternatively, silence this error by activating the `scalar` feature (eg `cargo install -F scalar ...`).
-See the readme at https://github.com/ragnargrootkoerkamp/ensure_simd for details."
-);
-
-/// Do a run-time check that AVX2 SIMD instructions are available when compiled into the binary.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+mod aarch64;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod compile_check;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod cpuid;
+#[cfg(all(
+    feature = "ctor",
+    any(target_os = "linux", target_os = "android", target_os = "freebsd")
+))]
+mod ctor;
+#[cfg(feature = "std")]
+mod demangle;
+mod detector;
+mod dispatch;
+#[cfg(feature = "std")]
+mod elf;
+#[cfg(feature = "std")]
+mod env;
+mod error;
+mod features;
+#[cfg(feature = "std")]
+mod fingerprint;
+#[cfg(feature = "std")]
+mod hint;
+#[cfg(target_arch = "aarch64")]
+mod hwcap;
+#[cfg(feature = "std")]
+mod inspect;
+mod level;
+#[cfg(feature = "load-check")]
+mod load_check;
+mod note;
+#[cfg(feature = "std")]
+mod policy;
+#[cfg(feature = "std")]
+mod scan;
+#[cfg(all(
+    feature = "sigill",
+    target_os = "linux",
+    any(target_env = "gnu", target_env = "musl"),
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod sigill;
+mod simd_cfg;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+mod token;
+#[cfg(feature = "std")]
+mod vectors;
+#[cfg(feature = "std")]
+mod x86;
+
+#[cfg(feature = "std")]
+pub use detector::with_detector;
+pub use detector::{FeatureDetector, HostCpu, MockCpu, check_simd_level_with, check_simd_with};
+pub use error::SimdError;
+pub use features::FeatureSet;
+#[cfg(feature = "std")]
+pub use fingerprint::{CpuFingerprint, FingerprintMatch};
+#[cfg(feature = "std")]
+pub use hint::upgrade_hint;
+#[cfg(feature = "std")]
+pub use inspect::{BinaryInfo, Compatibility, InspectError, inspect, inspect_bytes};
+pub use level::{ParseSimdLevelError, SimdLevel};
+#[cfg(feature = "load-check")]
+pub use load_check::load_check;
+pub use note::{NOTE_TYPE, build_note};
+#[cfg(feature = "std")]
+pub use policy::{FailurePolicy, set_failure_policy};
+#[cfg(feature = "std")]
+pub use scan::{ExtensionUse, ScanReport, scan, scan_bytes};
+#[cfg(all(
+    feature = "sigill",
+    target_os = "linux",
+    any(target_env = "gnu", target_env = "musl"),
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub use sigill::install_sigill_handler;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+pub use token::SimdToken;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub use token::{Avx2Token, Sse42Token, V2Token, V3Token, V4Token};
+#[cfg(target_arch = "aarch64")]
+pub use token::{NeonToken, Sve2Token, SveToken};
+#[cfg(feature = "std")]
+pub use vectors::{FunctionVectors, VectorReport, vectors, vectors_bytes};
+
+/// The `#[ensure_simd::main]` attribute, which runs the check as the first statement of `main`.
+///
+/// This calls [`ensure_simd`], so it also honors `ENSURE_SIMD_SKIP`, and prints the same warnings and hints.
+/// For an `async fn main`, place it below the attribute that runs it, e.g. `#[tokio::main]`,
+/// so that the check runs before the async runtime starts.
+///
+/// ```ignore
+/// #[ensure_simd::main]
+/// fn main() {}
+///
+/// // With a custom required level and failure policy.
+/// #[tokio::main]
+/// #[ensure_simd::main(level = "v4", on_failure = "exit", exit_code = 2)]
+/// async fn main() {}
+/// ```
+///
+/// Supported options:
+/// - `level`: the [`SimdLevel`] to require instead of [`SimdLevel::required`]:
+///   `"v1"`, `"v2"`, `"v3"`, `"v4"`, `"neon"`, `"sve"`, or `"sve2"`.
+/// - `on_failure`: the [`FailurePolicy`]: `"exit"`, `"panic"`, `"abort"`, `"warn"`, or `"current"` (default),
+///   which uses [`FailurePolicy::current`], as set by [`set_failure_policy`].
+/// - `exit_code`: the exit code for `on_failure = "exit"`, by default 1.
+#[cfg(feature = "macros")]
+pub use ensure_simd_macros::main;
+
+/// The `#[ensure_simd::multiversion(...)]` attribute, which compiles a function once per SIMD level
+/// and calls the best version supported by the CPU.
+///
+/// ```ignore
+/// #[ensure_simd::multiversion(v2, v3, v4)]
+/// fn sum(xs: &[f32]) -> f32 {
+///     xs.iter().sum()
+/// }
+/// ```
+///
+/// Each listed level gets a copy of the function compiled with `#[target_feature]` for that level,
+/// next to a baseline copy compiled with the target features of the crate.
+/// The level is detected once, at the first call, and respects the `ENSURE_SIMD_LEVEL` and
+/// `ENSURE_SIMD_DISABLE` environment variables.
+///
+/// Supported levels are `v2`, `v3`, and `v4` on x86-64, and `neon`, `sve`, and `sve2` on aarch64.
+/// Copies for other architectures are left out.
+/// Only free functions are supported: not methods taking `self`, and not `const` or `async` functions.
+///
+/// To build a portable binary, enable the `scalar` feature so that the crate does not require AVX2
+/// at compile time: `features = ["macros", "scalar"]`.
+#[cfg(feature = "macros")]
+pub use ensure_simd_macros::multiversion;
+
+/// Implementation details of the macros. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::dispatch::{Dispatch, has_target_features};
+
+    /// [`ensure_simd`](crate::ensure_simd) with the options of `#[ensure_simd::main]`:
+    /// the level to require instead of [`SimdLevel::required`](crate::SimdLevel::required),
+    /// and the policy to use instead of [`FailurePolicy::current`](crate::FailurePolicy::current).
+    #[cfg(feature = "std")]
+    pub fn ensure_simd(
+        level: Option<crate::SimdLevel>,
+        policy: Option<&'static crate::FailurePolicy>,
+    ) {
+        crate::ensure_simd_with(level, policy);
+    }
+}
+
+/// Do a run-time check that all SIMD instructions compiled into the binary are supported by the CPU.
+///
+/// This checks every x86 target feature that was enabled at compile time (e.g. AVX2, FMA, BMI2,
+/// and AVX-512 when building with `-C target-cpu=native` on a recent CPU),
+/// as well as the features of [`SimdLevel::required`] unless the `scalar` feature is enabled.
+/// In debug and documentation builds, where the compile-time check is skipped, the required level
+/// is not enforced either, and only the features enabled at compile time are checked.
+/// On failure, the returned [`SimdError`] lists the required, detected, and missing features.
+///
+/// Unlike [`ensure_simd`], this does not exit the process, so the caller can decide how to handle the error.
+///
+/// Note that this function is itself compiled with the enabled target features, and building the
+/// error may use them on a CPU that lacks them. [`ensure_simd`] first checks the enabled target
+/// features using only code that also runs on such CPUs.
+///
+/// Use [`check_simd_with`] or [`with_detector`] to check against a simulated CPU instead.
+pub fn check_simd() -> Result<(), SimdError> {
+    check_features(check_simd_required(), FeatureSet::detected())
+}
+
+/// The features checked by [`check_simd`].
+fn check_simd_required() -> FeatureSet {
+    if cfg!(feature = "scalar") {
+        FeatureSet::compiled()
+    } else {
+        check_simd_level_required(SimdLevel::required())
+    }
+}
+
+/// Like [`check_simd`], but require the given level instead of [`SimdLevel::required`].
+///
+/// Levels of other architectures than the current one are ignored, and, as for [`check_simd`],
+/// so is the level in debug and documentation builds.
+pub fn check_simd_level(level: SimdLevel) -> Result<(), SimdError> {
+    check_features(check_simd_level_required(level), FeatureSet::detected())
+}
+
+/// The features checked by [`check_simd_level`].
+fn check_simd_level_required(level: SimdLevel) -> FeatureSet {
+    // Debug and documentation builds skip the compile-time check, so the level's features may not
+    // be compiled in, and requiring them would fail on CPUs that run the binary just fine.
+    if cfg!(any(debug_assertions, doc)) {
+        FeatureSet::compiled()
+    } else {
+        FeatureSet::compiled().union(&level.feature_set())
+    }
+}
+
+fn check_features(required: FeatureSet, detected: FeatureSet) -> Result<(), SimdError> {
+    if required.difference(&detected).is_empty() {
+        Ok(())
+    } else {
+        Err(SimdError { required, detected })
+    }
+}
+
+/// Like [`check_simd`], but also write the error message to `out` on failure.
+///
+/// This is the `no_std` counterpart of [`ensure_simd`]: the report goes to any [`core::fmt::Write`] sink,
+/// e.g. a serial console or kernel log, and the caller decides what to do with the returned error.
+pub fn check_simd_report(out: &mut impl core::fmt::Write) -> Result<(), SimdError> {
+    let result = check_simd();
+    if let Err(err) = &result {
+        // The check itself failed, so an unreportable error should not hide it.
+        let _ = writeln!(out, "{err}");
+    }
+    result
+}
+
+/// Do a run-time check that all SIMD instructions compiled into the binary are supported by the CPU,
+/// and exit with an error message listing the missing features otherwise.
+///
+/// What happens on failure can be changed using [`set_failure_policy`].
+///
+/// For binaries built with `-C target-cpu=native`, this also prints a warning when
+/// running on a different CPU than the build machine. See [`CpuFingerprint`].
+/// With the `hint` feature, it prints a one-time hint when the binary was built without the
+/// required SIMD level, but the CPU supports it. See [`upgrade_hint`].
 ///
 /// Ideally call this at the very start of your `main` function, to avoid hitting illegal AVX2 instructions during e.g. argument parsing.
+/// Alternatively, enable the `ctor` feature to run this automatically before `main` on ELF platforms.
 ///
-/// (NEON instructions are always available on ARM targets, so no check is needed.)
+/// Set `ENSURE_SIMD_SKIP=1` to skip the check, e.g. when it is wrong about the CPU.
+///
+/// See [`check_simd`] for a variant that returns the error instead.
+#[cfg(feature = "std")]
 pub fn ensure_simd() {
-    #[cfg(target_feature = "avx2")]
-    {
-        if !is_x86_feature_detected!("avx2") {
-            eprintln!(
-                "
-This binary was compiled with AVX2 instructions enabled, but your CPU does not support this.
-Please run on a CPU that supports AVX2, or build from source with the `-F scalar` feature enabled.
-See the readme at https://github.com/ragnargrootkoerkamp/ensure_simd for details.
-"
-            );
-            std::process::exit(1);
+    ensure_simd_with(None, None);
+}
+
+/// [`ensure_simd`], optionally with a level to require and a policy to use instead of the defaults.
+#[cfg(feature = "std")]
+fn ensure_simd_with(level: Option<SimdLevel>, policy: Option<&FailurePolicy>) {
+    // The full check is compiled with the enabled target features as well, so first check those
+    // using only code that also runs on CPUs without them.
+    if !features::compiled_supported() {
+        if !env::skip() {
+            policy::handle_unsupported(policy);
+        }
+        return;
+    }
+    if env::skip() {
+        return;
+    }
+    let result = match level {
+        Some(level) => check_simd_level(level),
+        None => check_simd(),
+    };
+    match result {
+        Err(err) => policy
+            .copied()
+            .unwrap_or_else(FailurePolicy::current)
+            .handle(&err),
+        Ok(()) => {
+            if let Some(warning) = fingerprint::mismatch_warning() {
+                eprintln!("\n{warning}\n");
+            }
+            if cfg!(feature = "hint") {
+                hint::print_upgrade_hint();
+            }
         }
     }
 }
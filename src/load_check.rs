@@ -0,0 +1,52 @@
+//! A check for shared libraries, such as Python extension modules, that has no `main` to run from
+//! and must not exit the host process.
+//!
+//! The check runs when the library is loaded, via an ELF `.init_array` constructor,
+//! and its result is recorded until the library's init function asks for it.
+//! On other platforms, the check runs on the first call of [`load_check`].
+
+use std::sync::OnceLock;
+
+use crate::features::compiled_supported;
+use crate::{SimdError, check_simd};
+
+static RESULT: OnceLock<Result<(), SimdError>> = OnceLock::new();
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+#[used]
+#[unsafe(link_section = ".init_array.00101")]
+static LOAD_CHECK: extern "C" fn() = record;
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+extern "C" fn record() {
+    // The full check is compiled with the enabled target features as well, so first check those
+    // using only code that also runs on CPUs without them.
+    if compiled_supported() {
+        RESULT.get_or_init(check_simd);
+    }
+}
+
+/// The result of the run-time check done when this library was loaded.
+///
+/// Unlike [`ensure_simd`](crate::ensure_simd), a failed check does not exit the process.
+/// Call this first thing in the init function of the library, before any SIMD code runs,
+/// and turn the error into the error type of the host, e.g. for a `pyo3` module:
+///
+/// ```ignore
+/// #[pymodule]
+/// fn my_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+///     ensure_simd::load_check().map_err(|err| PyImportError::new_err(err.to_string()))?;
+///     // ...
+/// }
+/// ```
+///
+/// Do not enable the `ctor` feature for libraries, since that exits the host process on failure.
+///
+/// On a CPU without all target features enabled at compile time, the error displays a message
+/// rendered at compile time, which lists all enabled features.
+pub fn load_check() -> Result<(), SimdError> {
+    if !compiled_supported() {
+        return Err(SimdError::unsupported());
+    }
+    *RESULT.get_or_init(check_simd)
+}
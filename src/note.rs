@@ -0,0 +1,109 @@
+//! Build metadata, embedded in binaries as a `.note.ensure_simd` ELF note.
+//!
+//! The note can be inspected without running the binary, e.g. using `readelf -n` or `ensure-simd inspect`.
+//! Its owner is `ensure_simd`, its type is [`NOTE_TYPE`], and its description is the text of
+//! [`build_note`]: `key=value` lines, starting with `version=1`.
+
+use crate::SimdLevel;
+
+/// The type of the note. Incremented when the format of the description changes incompatibly.
+pub const NOTE_TYPE: u32 = 1;
+
+/// The owner name of the note, including the terminating NUL.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+const NAME: [u8; 12] = *b"ensure_simd\0";
+
+const PARTS: [&str; 11] = [
+    "version=1\ntarget=",
+    env!("ENSURE_SIMD_TARGET"),
+    "\nlevel=",
+    SimdLevel::compiled().name(),
+    "\nscalar=",
+    if cfg!(feature = "scalar") {
+        "true"
+    } else {
+        "false"
+    },
+    "\nrustc=",
+    env!("ENSURE_SIMD_RUSTC_VERSION"),
+    "\nfeatures=",
+    env!("ENSURE_SIMD_BUILD_TARGET_FEATURES"),
+    "\n",
+];
+
+const LEN: usize = {
+    let mut len = 0;
+    let mut i = 0;
+    while i < PARTS.len() {
+        len += PARTS[i].len();
+        i += 1;
+    }
+    len
+};
+
+/// The description, padded with zeros to a multiple of 4 bytes.
+const DESC: [u8; LEN.next_multiple_of(4)] = {
+    let mut desc = [0; LEN.next_multiple_of(4)];
+    let mut pos = 0;
+    let mut i = 0;
+    while i < PARTS.len() {
+        let part = PARTS[i].as_bytes();
+        let mut j = 0;
+        while j < part.len() {
+            desc[pos] = part[j];
+            pos += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+    desc
+};
+
+/// The layout of an ELF note.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+#[repr(C, align(4))]
+struct Note {
+    namesz: u32,
+    descsz: u32,
+    ty: u32,
+    name: [u8; 12],
+    desc: [u8; LEN.next_multiple_of(4)],
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+#[used]
+#[unsafe(link_section = ".note.ensure_simd")]
+static NOTE: Note = Note {
+    namesz: NAME.len() as u32,
+    descsz: LEN as u32,
+    ty: NOTE_TYPE,
+    name: NAME,
+    desc: DESC,
+};
+
+/// The build metadata embedded in the `.note.ensure_simd` ELF note:
+/// the target triple, the compiled [`SimdLevel`], whether the `scalar` feature is enabled,
+/// the `rustc` version, and all enabled target features. For example:
+///
+/// ```text
+/// version=1
+/// target=x86_64-unknown-linux-gnu
+/// level=x86-64-v3
+/// scalar=false
+/// rustc=rustc 1.95.0 (59807616e 2026-04-14)
+/// features=avx,avx2,bmi1,bmi2,cmpxchg16b,f16c,fma,fxsr,lzcnt,movbe,popcnt,sse,sse2,...
+/// ```
+pub const fn build_note() -> &'static str {
+    match core::str::from_utf8(DESC.split_at(LEN).0) {
+        Ok(s) => s,
+        Err(_) => panic!("invalid utf-8"),
+    }
+}
+
+/// The value of `key` in the [`build_note`].
+pub(crate) fn build_note_value(key: &str) -> Option<&'static str> {
+    build_note().lines().find_map(|line| {
+        line.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
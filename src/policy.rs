@@ -0,0 +1,158 @@
+use std::io::Write;
+use std::sync::OnceLock;
+
+use crate::SimdError;
+use crate::error::UNSUPPORTED;
+
+/// What to do when the run-time SIMD check fails.
+#[derive(Clone, Copy, Debug)]
+pub enum FailurePolicy {
+    /// Print the error and exit the process with the given exit code.
+    Exit(i32),
+    /// Panic with the error message.
+    Panic,
+    /// Print the error and abort the process.
+    Abort,
+    /// Print the error as a warning and continue.
+    Warn,
+    /// Call the given function, e.g. to log the error or raise it in another language,
+    /// and continue when it returns.
+    Callback(fn(&SimdError)),
+}
+
+/// The policy used by [`ensure_simd`](crate::ensure_simd), set by [`set_failure_policy`].
+static POLICY: OnceLock<FailurePolicy> = OnceLock::new();
+
+/// Set the [`FailurePolicy`] used by [`ensure_simd`](crate::ensure_simd) and by `#[ensure_simd::main]`
+/// without an `on_failure` option.
+///
+/// The check run before `main` by the `ctor` feature always uses the default policy,
+/// since it runs before this can be called.
+///
+/// The policy can only be set once, and must be set before the first failing check.
+/// Returns the policy that is already in use otherwise.
+pub fn set_failure_policy(policy: FailurePolicy) -> Result<(), FailurePolicy> {
+    POLICY.set(policy).map_err(|_| *POLICY.get().unwrap())
+}
+
+impl Default for FailurePolicy {
+    /// Exit with code 1, as done by [`ensure_simd`](crate::ensure_simd).
+    fn default() -> Self {
+        FailurePolicy::Exit(1)
+    }
+}
+
+impl FailurePolicy {
+    /// The policy set by [`set_failure_policy`], or the [default](FailurePolicy::default) otherwise.
+    pub fn current() -> Self {
+        *POLICY.get_or_init(Self::default)
+    }
+
+    /// Handle a failed check according to this policy.
+    ///
+    /// Only returns for [`FailurePolicy::Warn`] and [`FailurePolicy::Callback`].
+    pub fn handle(self, err: &SimdError) {
+        match self {
+            FailurePolicy::Exit(code) => {
+                eprintln!("\n{err}\n");
+                std::process::exit(code);
+            }
+            FailurePolicy::Panic => panic!("{err}"),
+            FailurePolicy::Abort => {
+                eprintln!("\n{err}\n");
+                std::process::abort();
+            }
+            FailurePolicy::Warn => eprintln!("\nWarning: {err}\n"),
+            FailurePolicy::Callback(f) => f(err),
+        }
+    }
+}
+
+/// Handle a CPU that lacks target features enabled at compile time, as found by the check that
+/// runs before the full check, which itself may not run on such a CPU.
+///
+/// Uses the given policy, or the one set by [`set_failure_policy`] otherwise.
+///
+/// This prints the message rendered at compile time instead of formatting the error, and reads the
+/// policy by reference, since even copying it may use e.g. AVX instructions.
+#[inline(never)]
+pub(crate) fn handle_unsupported(policy: Option<&FailurePolicy>) {
+    match policy.or_else(|| POLICY.get()) {
+        None => {
+            print_unsupported("");
+            std::process::exit(1);
+        }
+        Some(&FailurePolicy::Exit(code)) => {
+            print_unsupported("");
+            std::process::exit(code);
+        }
+        // `panic_any` rather than `panic!`, to not format the message with code of the crate.
+        Some(FailurePolicy::Panic) => std::panic::panic_any(UNSUPPORTED),
+        Some(FailurePolicy::Abort) => {
+            print_unsupported("");
+            std::process::abort();
+        }
+        Some(FailurePolicy::Warn) => print_unsupported("Warning: "),
+        Some(&FailurePolicy::Callback(f)) => unsupported_callback(f),
+    }
+}
+
+/// Call the callback with the error of the full check.
+///
+/// The callback is compiled with the enabled target features itself, so this may run code using
+/// them as well. It is kept out of [`handle_unsupported`], to keep the other policies safe.
+#[inline(never)]
+#[cold]
+fn unsupported_callback(f: fn(&SimdError)) {
+    if let Err(err) = crate::check_simd() {
+        f(&err);
+    }
+}
+
+/// Print [`UNSUPPORTED`] to stderr, without formatting.
+fn print_unsupported(prefix: &str) {
+    let mut stderr = std::io::stderr();
+    for part in ["\n", prefix, UNSUPPORTED, "\n\n"] {
+        let _ = stderr.write_all(part.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FeatureSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const ERR: SimdError = SimdError {
+        required: FeatureSet::ALL,
+        detected: FeatureSet::EMPTY,
+    };
+
+    #[test]
+    fn callback() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn count(err: &SimdError) {
+            assert_eq!(*err, ERR);
+            CALLS.fetch_add(1, Ordering::Relaxed);
+        }
+        FailurePolicy::Callback(count).handle(&ERR);
+        FailurePolicy::Callback(count).handle(&ERR);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn warn_returns() {
+        FailurePolicy::Warn.handle(&ERR);
+    }
+
+    #[test]
+    #[should_panic(expected = "This binary requires SIMD instructions")]
+    fn panic() {
+        FailurePolicy::Panic.handle(&ERR);
+    }
+
+    #[test]
+    fn default_exits_with_1() {
+        assert!(matches!(FailurePolicy::default(), FailurePolicy::Exit(1)));
+    }
+}
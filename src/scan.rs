@@ -0,0 +1,123 @@
+//! Static scan of the machine code of other binaries, to find the ISA extensions they actually use.
+
+use crate::demangle::demangle;
+use crate::elf::{EM_X86_64, Elf};
+use crate::{InspectError, SimdLevel};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// The ISA extensions used by the code of a binary, as returned by [`scan`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScanReport {
+    /// The extensions beyond the x86-64 baseline, ordered by level and name.
+    pub extensions: Vec<ExtensionUse>,
+}
+
+/// The instructions of one ISA extension in a binary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionUse {
+    /// The target feature, e.g. `avx2` or `avx512f`.
+    ///
+    /// AVX-512 instructions are reported by their extension, e.g. `avx512bw`, and as `avx512vl` for
+    /// `avx512f` instructions on 128-bit or 256-bit vectors. Those of the other extensions on such
+    /// vectors require `avx512vl` as well.
+    pub feature: &'static str,
+    /// The lowest level that includes the feature, or `None` for features outside all levels, such as `aes`.
+    pub level: Option<SimdLevel>,
+    /// The number of instructions.
+    pub instructions: usize,
+    /// The demangled names of the functions containing them, sorted.
+    ///
+    /// Code outside function symbols, e.g. in stripped binaries, is reported by section name, e.g. `.text`.
+    pub functions: Vec<String>,
+}
+
+impl ScanReport {
+    /// The highest level of which the binary contains instructions.
+    pub fn level(&self) -> SimdLevel {
+        self.extensions
+            .iter()
+            .filter_map(|e| e.level)
+            .max()
+            .unwrap_or(SimdLevel::X86_64V1)
+    }
+
+    /// The extensions that are not part of `level`, including those outside all levels.
+    pub fn beyond(&self, level: SimdLevel) -> impl Iterator<Item = &ExtensionUse> {
+        self.extensions.iter().filter(move |e| e.is_beyond(level))
+    }
+}
+
+impl ExtensionUse {
+    /// Whether the feature is not part of `level`.
+    pub fn is_beyond(&self, level: SimdLevel) -> bool {
+        self.level.is_none_or(|l| l > level)
+    }
+}
+
+/// Disassemble the executable sections of the x86-64 ELF binary at `path`, and report the
+/// ISA extensions it uses, and in which functions.
+///
+/// Unlike the metadata returned by [`inspect`](crate::inspect), this also finds instructions
+/// that were not intended, e.g. from inline assembly in a dependency. Note that code behind
+/// run-time detection, such as `#[multiversion]` clones, is reported as well.
+///
+/// ```no_run
+/// let report = ensure_simd::scan("target/release/tool")?;
+/// for e in report.beyond(ensure_simd::SimdLevel::X86_64V3) {
+///     eprintln!("{} used in {}", e.feature, e.functions.join(", "));
+/// }
+/// # Ok::<(), ensure_simd::InspectError>(())
+/// ```
+pub fn scan(path: impl AsRef<Path>) -> Result<ScanReport, InspectError> {
+    scan_bytes(&std::fs::read(path)?)
+}
+
+/// Like [`scan`], for the contents of a binary.
+pub fn scan_bytes(data: &[u8]) -> Result<ScanReport, InspectError> {
+    let elf = Elf::parse(data).ok_or(InspectError::NotElf)?;
+    if elf.machine != EM_X86_64 {
+        return Err(InspectError::UnsupportedMachine(elf.machine));
+    }
+    let mut uses: BTreeMap<&str, (usize, BTreeSet<&str>)> = BTreeMap::new();
+    for (name, code) in elf.function_code() {
+        let mut pos = 0;
+        while let Some(insn) = crate::x86::decode(&code[pos..]) {
+            if let Some(feature) = insn.feature {
+                let (count, functions) = uses.entry(feature).or_default();
+                *count += 1;
+                functions.insert(name);
+            }
+            pos += insn.len;
+        }
+    }
+
+    let mut extensions: Vec<ExtensionUse> = uses
+        .into_iter()
+        .map(|(feature, (instructions, functions))| {
+            let mut functions: Vec<String> = functions.into_iter().map(demangle).collect();
+            functions.sort();
+            functions.dedup();
+            ExtensionUse {
+                feature,
+                level: feature_level(feature),
+                instructions,
+                functions,
+            }
+        })
+        .collect();
+    extensions.sort_by_key(|e| (e.level.is_none(), e.level, e.feature));
+    Ok(ScanReport { extensions })
+}
+
+/// The lowest x86-64 level that includes `feature`.
+fn feature_level(feature: &str) -> Option<SimdLevel> {
+    [
+        SimdLevel::X86_64V1,
+        SimdLevel::X86_64V2,
+        SimdLevel::X86_64V3,
+        SimdLevel::X86_64V4,
+    ]
+    .into_iter()
+    .find(|level| level.target_features().contains(&feature))
+}
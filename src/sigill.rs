@@ -0,0 +1,125 @@
+//! A `SIGILL` handler that explains illegal-instruction crashes caused by missing SIMD support.
+//!
+//! `libc` functions are declared manually to keep this crate free of dependencies.
+//! The `sigaction` layout below matches glibc and musl.
+
+use core::ffi::{c_int, c_void};
+use std::sync::OnceLock;
+
+use crate::error::UNSUPPORTED;
+use crate::{FeatureSet, SimdLevel, check_simd};
+
+const SIGILL: c_int = 4;
+const SA_SIGINFO: c_int = 4;
+const SA_RESETHAND: c_int = 0x8000_0000_u32 as c_int;
+
+#[repr(C)]
+struct SigAction {
+    sa_sigaction: usize,
+    sa_mask: [u64; 16],
+    sa_flags: c_int,
+    sa_restorer: usize,
+}
+
+#[repr(C)]
+struct SigInfo {
+    si_signo: c_int,
+    si_errno: c_int,
+    si_code: c_int,
+    si_addr: *mut c_void,
+}
+
+unsafe extern "C" {
+    fn sigaction(signum: c_int, act: *const SigAction, oldact: *mut SigAction) -> c_int;
+    fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+    fn _exit(status: c_int) -> !;
+}
+
+/// The explanation printed by the handler. Rendered up front, since the handler can not allocate.
+///
+/// Not set on CPUs that lack features enabled at compile time.
+static MESSAGE: OnceLock<String> = OnceLock::new();
+
+/// Install a `SIGILL` handler that prints the faulting address, the target features the binary
+/// was compiled with, and those missing on the current CPU, and then exits with code 1.
+///
+/// This covers illegal instructions hit before or despite the run-time check,
+/// instead of only showing `Illegal instruction (core dumped)`.
+/// Installing it multiple times is harmless.
+///
+/// When the `ctor` feature is also enabled, this is installed automatically before `main`.
+pub fn install_sigill_handler() {
+    let action = SigAction {
+        sa_sigaction: handler as *const () as usize,
+        sa_mask: [0; 16],
+        // Reset to the default handler, so that a second SIGILL in the handler itself dumps core.
+        sa_flags: SA_SIGINFO | SA_RESETHAND,
+        sa_restorer: 0,
+    };
+    // Installed before rendering the message, since that runs code compiled with the enabled
+    // target features, which may itself hit an illegal instruction.
+    // SAFETY: `action` is a valid `sigaction`, and the handler only uses async-signal-safe functions.
+    unsafe {
+        sigaction(SIGILL, &action, core::ptr::null_mut());
+    }
+    if !crate::features::compiled_supported() {
+        // The handler prints `UNSUPPORTED` instead.
+        return;
+    }
+    MESSAGE.get_or_init(|| {
+        let compiled = FeatureSet::compiled();
+        let reason = match check_simd() {
+            Err(err) => err.to_string(),
+            Ok(()) => "Your CPU does support all these features, so the illegal instruction is likely unrelated to SIMD support.
+See the readme at https://github.com/ragnargrootkoerkamp/ensure_simd for details."
+                .to_string(),
+        };
+        format!(
+            "This binary was compiled for {} with target features: {compiled}.
+{reason}
+
+",
+            SimdLevel::compiled()
+        )
+    });
+}
+
+extern "C" fn handler(_signum: c_int, info: *mut SigInfo, _context: *mut c_void) {
+    let addr = if info.is_null() {
+        0
+    } else {
+        // SAFETY: the kernel passes a valid `siginfo_t` for handlers installed with `SA_SIGINFO`.
+        unsafe { (*info).si_addr as usize }
+    };
+
+    let mut hex = *b"0x0000000000000000";
+    for (i, b) in hex[2..].iter_mut().rev().enumerate() {
+        let nibble = (addr.checked_shr(4 * i as u32).unwrap_or(0) & 0xf) as u8;
+        *b = if nibble < 10 {
+            b'0' + nibble
+        } else {
+            b'a' + nibble - 10
+        };
+    }
+
+    write_stderr(b"\nIllegal instruction at address ");
+    write_stderr(&hex);
+    write_stderr(b".\n");
+    match MESSAGE.get() {
+        Some(message) => write_stderr(message.as_bytes()),
+        // The CPU lacks features enabled at compile time, so the message was not rendered.
+        None => {
+            write_stderr(UNSUPPORTED.as_bytes());
+            write_stderr(b"\n\n");
+        }
+    }
+    // SAFETY: `_exit` is async-signal-safe, unlike `std::process::exit`.
+    unsafe { _exit(1) }
+}
+
+fn write_stderr(buf: &[u8]) {
+    // SAFETY: `write` is async-signal-safe, and `buf` is valid for `buf.len()` bytes.
+    unsafe {
+        write(2, buf.as_ptr().cast(), buf.len());
+    }
+}
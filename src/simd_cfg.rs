@@ -0,0 +1,110 @@
+//! The `simd_cfg!` macro, and the target features of each level it shares with [`SimdLevel`](crate::SimdLevel).
+
+/// Compile exactly one of several code arms, depending on the SIMD level enabled at compile time.
+///
+/// The first arm whose target features are all enabled is used, or the `_` arm otherwise.
+/// Arms can contain items or statements:
+///
+/// ```
+/// ensure_simd::simd_cfg! {
+///     v4 => { const LANES: usize = 16; },
+///     avx2 => { const LANES: usize = 8; },
+///     neon => { const LANES: usize = 4; },
+///     _ => { const LANES: usize = 1; },
+/// }
+/// # assert!(LANES > 0);
+/// ```
+///
+/// The keys are the levels `v1`, `v2`, `v3`, `v4`, `neon`, `sve`, and `sve2`, and the single
+/// features `avx2` and `avx512`. The levels use the same target features as [`SimdLevel::target_features`]
+/// and the compile-time check, so that e.g. the `v3` arm is used exactly when the `require-v3` check passes.
+///
+/// [`SimdLevel::target_features`]: crate::SimdLevel::target_features
+#[macro_export]
+macro_rules! simd_cfg {
+    ($($arms:tt)*) => {
+        $crate::__simd_cfg! { @arms () $($arms)* }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __simd_cfg {
+    (@arms ($($prev:meta,)*)) => {};
+    (@arms ($($prev:meta,)*) _ => { $($body:tt)* } $(,)?) => {
+        #[cfg(not(any($($prev,)*)))]
+        $crate::__simd_cfg! { @identity $($body)* }
+    };
+    (@arms ($($prev:meta,)*) $key:ident => { $($body:tt)* }, $($rest:tt)*) => {
+        $crate::__simd_level! { __simd_cfg @emit $key ($($prev,)*) { $($body)* } ($($rest)*) }
+    };
+    (@arms ($($prev:meta,)*) $key:ident => { $($body:tt)* } $($rest:tt)*) => {
+        $crate::__simd_level! { __simd_cfg @emit $key ($($prev,)*) { $($body)* } ($($rest)*) }
+    };
+    (@emit ($arch:meta) [$($feature:literal),*] ($($prev:meta,)*) { $($body:tt)* } ($($rest:tt)*)) => {
+        #[cfg(all($arch, $(target_feature = $feature,)* not(any($($prev,)*))))]
+        $crate::__simd_cfg! { @identity $($body)* }
+        $crate::__simd_cfg! { @arms ($($prev,)* all($arch, $(target_feature = $feature),*),) $($rest)* }
+    };
+    (@features ($arch:meta) [$($feature:literal),*]) => {
+        &[$($feature),*]
+    };
+    (@identity $($tokens:tt)*) => {
+        $($tokens)*
+    };
+}
+
+/// The architecture and target features of each `simd_cfg!` key, passed to `$crate::$callback! { @$tag ... }`.
+///
+/// This is the single definition of the features of each [`SimdLevel`](crate::SimdLevel).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __simd_level {
+    ($callback:ident @$tag:ident v1 $($args:tt)*) => {
+        $crate::$callback! { @$tag (any(target_arch = "x86", target_arch = "x86_64")) [
+            "sse", "sse2", "fxsr"
+        ] $($args)* }
+    };
+    ($callback:ident @$tag:ident v2 $($args:tt)*) => {
+        $crate::$callback! { @$tag (any(target_arch = "x86", target_arch = "x86_64")) [
+            "sse", "sse2", "fxsr",
+            "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "cmpxchg16b"
+        ] $($args)* }
+    };
+    ($callback:ident @$tag:ident v3 $($args:tt)*) => {
+        $crate::$callback! { @$tag (any(target_arch = "x86", target_arch = "x86_64")) [
+            "sse", "sse2", "fxsr",
+            "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "cmpxchg16b",
+            "avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "movbe", "xsave"
+        ] $($args)* }
+    };
+    ($callback:ident @$tag:ident v4 $($args:tt)*) => {
+        $crate::$callback! { @$tag (any(target_arch = "x86", target_arch = "x86_64")) [
+            "sse", "sse2", "fxsr",
+            "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "cmpxchg16b",
+            "avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "movbe", "xsave",
+            "avx512f", "avx512bw", "avx512cd", "avx512dq", "avx512vl"
+        ] $($args)* }
+    };
+    ($callback:ident @$tag:ident avx2 $($args:tt)*) => {
+        $crate::$callback! { @$tag (any(target_arch = "x86", target_arch = "x86_64")) ["avx2"] $($args)* }
+    };
+    ($callback:ident @$tag:ident avx512 $($args:tt)*) => {
+        $crate::$callback! { @$tag (any(target_arch = "x86", target_arch = "x86_64")) ["avx512f"] $($args)* }
+    };
+    ($callback:ident @$tag:ident neon $($args:tt)*) => {
+        $crate::$callback! { @$tag (target_arch = "aarch64") ["neon"] $($args)* }
+    };
+    ($callback:ident @$tag:ident sve $($args:tt)*) => {
+        $crate::$callback! { @$tag (target_arch = "aarch64") ["neon", "sve"] $($args)* }
+    };
+    ($callback:ident @$tag:ident sve2 $($args:tt)*) => {
+        $crate::$callback! { @$tag (target_arch = "aarch64") ["neon", "sve", "sve2"] $($args)* }
+    };
+    ($callback:ident @$tag:ident $key:ident $($args:tt)*) => {
+        ::core::compile_error!(::core::concat!(
+            "unknown `simd_cfg!` key `", ::core::stringify!($key),
+            "`; expected one of `v1`, `v2`, `v3`, `v4`, `avx2`, `avx512`, `neon`, `sve`, `sve2`, or `_`"
+        ));
+    };
+}
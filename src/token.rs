@@ -0,0 +1,166 @@
+//! Zero-sized tokens proving that the CPU supports a set of target features.
+//!
+//! A token can only be obtained from a successful run-time check, or for free when its features
+//! are enabled at compile time. Safe wrappers around `#[target_feature]` functions can take a token
+//! as argument, instead of requiring `unsafe` at every call site:
+//!
+//! ```
+//! # #[cfg(any(target_arch = "x86", target_arch = "x86_64"))] {
+//! use ensure_simd::Avx2Token;
+//!
+//! #[target_feature(enable = "avx2")]
+//! fn sum_avx2(xs: &[u32]) -> u32 {
+//!     xs.iter().sum()
+//! }
+//!
+//! fn sum(_: Avx2Token, xs: &[u32]) -> u32 {
+//!     // SAFETY: the token proves that the CPU supports AVX2.
+//!     unsafe { sum_avx2(xs) }
+//! }
+//!
+//! if let Ok(token) = Avx2Token::check() {
+//!     assert_eq!(sum(token, &[1, 2, 3]), 6);
+//! }
+//! # }
+//! ```
+
+use crate::{FeatureSet, SimdError};
+
+/// A zero-sized proof that the CPU supports [`TARGET_FEATURES`](SimdToken::TARGET_FEATURES).
+///
+/// # Safety
+/// Implementations must only be constructible when all target features are supported.
+pub unsafe trait SimdToken: Copy + core::fmt::Debug {
+    /// The target features this token proves, as used in `#[target_feature(enable = "...")]`.
+    const TARGET_FEATURES: &'static [&'static str];
+
+    /// Check at run time that the CPU supports the features of this token.
+    fn check() -> Result<Self, SimdError>;
+
+    /// The token, if its features are enabled at compile time.
+    fn compiled() -> Option<Self>;
+}
+
+/// The detected features that are actually supported by the CPU.
+///
+/// [`FeatureSet::detected`] can be overridden to report more features than the CPU has,
+/// e.g. by [`with_detector`](crate::with_detector), which must not result in a token.
+fn supported() -> FeatureSet {
+    let detected = FeatureSet::detected();
+    #[cfg(feature = "std")]
+    let detected = detected.intersection(&crate::FeatureDetector::detect(&crate::HostCpu));
+    detected
+}
+
+macro_rules! tokens {
+    ($(
+        $(#[$attr:meta])*
+        $token:ident = [$($feature:literal),*] $(=> $($into:ident),*)?;
+    )*) => {$(
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub struct $token {
+            _private: (),
+        }
+
+        impl $token {
+            const FEATURES: FeatureSet = FeatureSet::from_names(&[$($feature),*]);
+
+            /// Check at run time that the CPU supports the features of this token.
+            pub fn check() -> Result<Self, SimdError> {
+                let detected = supported();
+                if detected.is_superset(&Self::FEATURES) {
+                    Ok(Self { _private: () })
+                } else {
+                    Err(SimdError { required: Self::FEATURES, detected })
+                }
+            }
+
+            /// The token, if its features are enabled at compile time. This needs no run-time check.
+            pub const fn compiled() -> Option<Self> {
+                if FeatureSet::compiled().is_superset(&Self::FEATURES) {
+                    Some(Self { _private: () })
+                } else {
+                    None
+                }
+            }
+
+            /// Create the token without checking.
+            ///
+            /// # Safety
+            /// The CPU must support all features of this token.
+            pub const unsafe fn new_unchecked() -> Self {
+                Self { _private: () }
+            }
+        }
+
+        // SAFETY: the token can only be constructed after checking its features.
+        unsafe impl SimdToken for $token {
+            const TARGET_FEATURES: &'static [&'static str] = &[$($feature),*];
+
+            fn check() -> Result<Self, SimdError> {
+                Self::check()
+            }
+
+            fn compiled() -> Option<Self> {
+                Self::compiled()
+            }
+        }
+
+        $($(
+            impl From<$token> for $into {
+                fn from(_: $token) -> Self {
+                    Self { _private: () }
+                }
+            }
+        )*)?
+    )*};
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+tokens! {
+    /// SSE4.2.
+    Sse42Token = ["sse4.2"];
+    /// AVX2. Note that [`V3Token`] also covers FMA and BMI.
+    Avx2Token = ["avx2"];
+    /// The features of [`SimdLevel::X86_64V2`](crate::SimdLevel::X86_64V2).
+    V2Token = ["sse", "sse2", "fxsr", "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "cmpxchg16b"]
+        => Sse42Token;
+    /// The features of [`SimdLevel::X86_64V3`](crate::SimdLevel::X86_64V3).
+    V3Token = [
+        "sse", "sse2", "fxsr", "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "cmpxchg16b",
+        "avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "movbe", "xsave"
+    ] => V2Token, Sse42Token, Avx2Token;
+    /// The features of [`SimdLevel::X86_64V4`](crate::SimdLevel::X86_64V4).
+    V4Token = [
+        "sse", "sse2", "fxsr", "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "cmpxchg16b",
+        "avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "movbe", "xsave",
+        "avx512f", "avx512bw", "avx512cd", "avx512dq", "avx512vl"
+    ] => V3Token, V2Token, Sse42Token, Avx2Token;
+}
+
+#[cfg(target_arch = "aarch64")]
+tokens! {
+    /// The features of [`SimdLevel::Neon`](crate::SimdLevel::Neon).
+    NeonToken = ["neon"];
+    /// The features of [`SimdLevel::Sve`](crate::SimdLevel::Sve).
+    SveToken = ["neon", "sve"] => NeonToken;
+    /// The features of [`SimdLevel::Sve2`](crate::SimdLevel::Sve2).
+    Sve2Token = ["neon", "sve", "sve2"] => SveToken, NeonToken;
+}
+
+/// The level tokens must prove exactly the features of their level.
+const _: () = {
+    use crate::SimdLevel;
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        assert!(V2Token::FEATURES.bits() == SimdLevel::X86_64V2.feature_set().bits());
+        assert!(V3Token::FEATURES.bits() == SimdLevel::X86_64V3.feature_set().bits());
+        assert!(V4Token::FEATURES.bits() == SimdLevel::X86_64V4.feature_set().bits());
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        assert!(SveToken::FEATURES.bits() == SimdLevel::Sve.feature_set().bits());
+        assert!(Sve2Token::FEATURES.bits() == SimdLevel::Sve2.feature_set().bits());
+    }
+};
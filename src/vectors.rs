@@ -0,0 +1,213 @@
+//! Vectorization report: the width of the SIMD registers used by each function of a binary.
+
+use crate::InspectError;
+use crate::demangle::demangle;
+use crate::elf::{EM_AARCH64, EM_X86_64, Elf};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The width of the SIMD registers an instruction computes on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Width {
+    /// A single element, e.g. `addss` or `fadd s0, s1, s2`.
+    Scalar,
+    Bits64,
+    Bits128,
+    Bits256,
+    Bits512,
+    /// SVE, of implementation-defined width.
+    Scalable,
+}
+
+/// The SIMD instructions of the functions of a binary, as returned by [`vectors`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VectorReport {
+    /// The architecture, as in `cfg(target_arch)`: `x86_64` or `aarch64`.
+    pub arch: &'static str,
+    /// All functions, sorted by demangled name.
+    pub functions: Vec<FunctionVectors>,
+}
+
+/// The number of SIMD instructions of each width in a function.
+///
+/// Loads, stores, and register moves are not counted, since they are also used to copy memory
+/// in scalar code. For example, a 32-byte copy uses `ymm` registers in any `x86-64-v3` build.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionVectors {
+    /// The demangled name.
+    pub name: String,
+    /// The number of instructions, including non-SIMD instructions.
+    pub instructions: usize,
+    /// Instructions on a single element, e.g. `addss` on x86 or `fadd s0, s1, s2` on aarch64.
+    pub scalar: usize,
+    /// Instructions on MMX registers, or on NEON `d` registers.
+    pub bits64: usize,
+    /// Instructions on `xmm` registers, or on NEON `q` registers.
+    pub bits128: usize,
+    /// Instructions on `ymm` registers.
+    pub bits256: usize,
+    /// Instructions on `zmm` registers.
+    pub bits512: usize,
+    /// SVE instructions on `z` registers, which are at least 128 bits wide.
+    pub scalable: usize,
+}
+
+impl VectorReport {
+    /// The minimum vector width of the intended fast path: 256 bits (AVX2) on x86-64,
+    /// and 128 bits (NEON) on aarch64.
+    pub fn default_min_bits(&self) -> u32 {
+        if self.arch == "x86_64" { 256 } else { 128 }
+    }
+
+    /// The functions whose name matches `pattern`.
+    ///
+    /// A pattern matches the full demangled name, or its last path segments: `sum` matches
+    /// `my_crate::sum`. `*` matches any sequence of characters, e.g. `my_crate::sum*` also matches
+    /// the `#[multiversion]` clones `my_crate::sum::__ensure_simd_v3` etc.
+    pub fn matching(&self, pattern: &str) -> impl Iterator<Item = &FunctionVectors> {
+        let suffix = format!("*::{pattern}");
+        self.functions
+            .iter()
+            .filter(move |f| glob(pattern, &f.name) || glob(&suffix, &f.name))
+    }
+}
+
+impl FunctionVectors {
+    /// Whether the function contains SIMD instructions on vectors of at least `min_bits` bits.
+    ///
+    /// Functions that only contain scalar or narrower SIMD code, e.g. because `wide` or
+    /// `portable-simd` fell back to 128-bit vectors, are not.
+    pub fn is_vectorized(&self, min_bits: u32) -> bool {
+        [
+            (64, self.bits64),
+            (128, self.bits128 + self.scalable),
+            (256, self.bits256),
+            (512, self.bits512),
+        ]
+        .iter()
+        .any(|&(bits, count)| bits >= min_bits && count > 0)
+    }
+
+    /// The width in bits of the widest SIMD instructions, or 0 when there are none.
+    ///
+    /// SVE instructions count as 128 bits, the minimum SVE vector length.
+    pub fn widest_bits(&self) -> u32 {
+        [
+            (512, self.bits512),
+            (256, self.bits256),
+            (128, self.bits128 + self.scalable),
+            (64, self.bits64),
+        ]
+        .iter()
+        .find(|&&(_, count)| count > 0)
+        .map_or(0, |&(bits, _)| bits)
+    }
+
+    fn add(&mut self, width: Option<Width>) {
+        self.instructions += 1;
+        match width {
+            None => {}
+            Some(Width::Scalar) => self.scalar += 1,
+            Some(Width::Bits64) => self.bits64 += 1,
+            Some(Width::Bits128) => self.bits128 += 1,
+            Some(Width::Bits256) => self.bits256 += 1,
+            Some(Width::Bits512) => self.bits512 += 1,
+            Some(Width::Scalable) => self.scalable += 1,
+        }
+    }
+}
+
+/// Disassemble the x86-64 or aarch64 ELF binary at `path`, and count the SIMD instructions of
+/// each width in each function.
+///
+/// This confirms that hot functions use the intended fast path, e.g. `ymm` registers with AVX2,
+/// rather than silently falling back to scalar or 128-bit code.
+///
+/// ```no_run
+/// let report = ensure_simd::vectors("target/release/tool")?;
+/// for f in report.matching("my_crate::hot_loop") {
+///     if !f.is_vectorized(report.default_min_bits()) {
+///         eprintln!("{} is not vectorized", f.name);
+///     }
+/// }
+/// # Ok::<(), ensure_simd::InspectError>(())
+/// ```
+pub fn vectors(path: impl AsRef<Path>) -> Result<VectorReport, InspectError> {
+    vectors_bytes(&std::fs::read(path)?)
+}
+
+/// Like [`vectors`], for the contents of a binary.
+pub fn vectors_bytes(data: &[u8]) -> Result<VectorReport, InspectError> {
+    let elf = Elf::parse(data).ok_or(InspectError::NotElf)?;
+    let arch = match elf.machine {
+        EM_X86_64 => "x86_64",
+        EM_AARCH64 => "aarch64",
+        machine => return Err(InspectError::UnsupportedMachine(machine)),
+    };
+
+    let mut functions: BTreeMap<String, FunctionVectors> = BTreeMap::new();
+    for (name, code) in elf.function_code() {
+        let name = demangle(name);
+        let function = functions.entry(name.clone()).or_default();
+        function.name = name;
+        if arch == "x86_64" {
+            let mut pos = 0;
+            while let Some(insn) = crate::x86::decode(&code[pos..]) {
+                function.add(insn.width);
+                pos += insn.len;
+            }
+        } else {
+            // A64 instructions are always little-endian.
+            for insn in code.chunks_exact(4) {
+                let insn = u32::from_le_bytes(insn.try_into().unwrap());
+                function.add(crate::aarch64::width(insn));
+            }
+        }
+    }
+    Ok(VectorReport {
+        arch,
+        functions: functions.into_values().collect(),
+    })
+}
+
+/// Whether `text` matches `pattern`, in which `*` matches any sequence of characters.
+fn glob(pattern: &str, text: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(mut text) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match text.find(part) {
+            Some(i) => text = &text[i + part.len()..],
+            None => return false,
+        }
+    }
+    text.len() >= last.len() && text.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches() {
+        assert!(glob("main", "main"));
+        assert!(!glob("main", "main2"));
+        assert!(glob("*", ""));
+        assert!(glob("tool::*", "tool::main"));
+        assert!(glob("*::sum", "tool::simd::sum"));
+        assert!(glob("tool::*::sum", "tool::simd::sum"));
+        assert!(glob("*simd*", "tool::simd::sum"));
+        assert!(glob("a*b*c", "abbc"));
+        assert!(!glob("tool::*", "other::main"));
+        assert!(!glob("*::sum", "tool::sum2"));
+        assert!(!glob("a*b*c", "acb"));
+        // The prefix and suffix may not overlap.
+        assert!(!glob("a*a", "a"));
+        assert!(!glob("ab*bc", "abc"));
+    }
+}
@@ -0,0 +1,775 @@
+//! A minimal x86-64 instruction decoder, for finding the ISA extensions used by a binary.
+//!
+//! Only the length of each instruction, the extension it belongs to, and the width of the vector
+//! registers it computes on are decoded.
+//! Instructions of the x86-64 baseline (including x87, MMX, SSE, and SSE2) have no extension.
+
+use crate::vectors::Width;
+
+/// A decoded instruction.
+pub(crate) struct Insn {
+    /// The length in bytes.
+    pub len: usize,
+    /// The target feature required by the instruction, if it is not part of the baseline.
+    pub feature: Option<&'static str>,
+    /// The width of the vector registers, for SIMD instructions other than moves.
+    pub width: Option<Width>,
+}
+
+/// The encoding of the opcode of an instruction.
+#[derive(Clone, Copy)]
+enum Encoding {
+    Legacy,
+    Vex { pp: u8, l: u8 },
+    Evex { pp: u8, ll: u8, b: bool, w: bool },
+    Xop,
+}
+
+/// Decode the instruction at the start of `code`, or return `None` when it is truncated.
+pub(crate) fn decode(code: &[u8]) -> Option<Insn> {
+    let mut i = 0;
+    let mut p66 = false;
+    let mut rep = None;
+    let mut addr32 = false;
+    // Legacy prefixes.
+    loop {
+        match *code.get(i)? {
+            0x66 => p66 = true,
+            0x67 => addr32 = true,
+            b @ (0xF2 | 0xF3) => rep = Some(b),
+            0xF0 | 0x2E | 0x36 | 0x3E | 0x26 | 0x64 | 0x65 => {}
+            _ => break,
+        }
+        i += 1;
+        // Instructions are at most 15 bytes long.
+        if i >= 15 {
+            return Some(Insn {
+                len: 1,
+                feature: None,
+                width: None,
+            });
+        }
+    }
+    let mut rex_w = false;
+    let mut apx = false;
+    let b = *code.get(i)?;
+    let rex = b & 0xF0 == 0x40;
+    if rex {
+        rex_w = b & 8 != 0;
+        i += 1;
+    }
+
+    let b = *code.get(i)?;
+    let (encoding, map) = match b {
+        // Invalid after a REX prefix.
+        0xC4 | 0xC5 | 0x62 if rex => (Encoding::Legacy, 0),
+        0xC5 => {
+            let p = *code.get(i + 1)?;
+            i += 2;
+            (
+                Encoding::Vex {
+                    pp: p & 3,
+                    l: (p >> 2) & 1,
+                },
+                1,
+            )
+        }
+        0xC4 => {
+            let (p0, p1) = (*code.get(i + 1)?, *code.get(i + 2)?);
+            i += 3;
+            (
+                Encoding::Vex {
+                    pp: p1 & 3,
+                    l: (p1 >> 2) & 1,
+                },
+                p0 & 0x1F,
+            )
+        }
+        0x62 => {
+            let (p0, p1, p2) = (*code.get(i + 1)?, *code.get(i + 2)?, *code.get(i + 3)?);
+            i += 4;
+            let evex = Encoding::Evex {
+                pp: p1 & 3,
+                ll: (p2 >> 5) & 3,
+                b: p2 & 0x10 != 0,
+                w: p1 & 0x80 != 0,
+            };
+            (evex, p0 & 7)
+        }
+        0x8F if code.get(i + 1)? & 0x1F >= 8 => {
+            let p0 = *code.get(i + 1)?;
+            i += 3;
+            (Encoding::Xop, p0 & 0x1F)
+        }
+        // REX2, from APX.
+        0xD5 => {
+            let p = *code.get(i + 1)?;
+            rex_w = p & 8 != 0;
+            apx = true;
+            i += 2;
+            (Encoding::Legacy, p >> 7)
+        }
+        0x0F => match *code.get(i + 1)? {
+            0x38 => {
+                i += 2;
+                (Encoding::Legacy, 2)
+            }
+            0x3A => {
+                i += 2;
+                (Encoding::Legacy, 3)
+            }
+            _ => {
+                i += 1;
+                (Encoding::Legacy, 1)
+            }
+        },
+        _ => (Encoding::Legacy, 0),
+    };
+    let op = *code.get(i)?;
+    i += 1;
+
+    let has_modrm = match (encoding, map) {
+        (Encoding::Legacy, 0) => match op {
+            0x00..=0x3F => op & 7 < 4,
+            0x62 | 0x63 | 0x69 | 0x6B | 0x80..=0x8F | 0xC0 | 0xC1 | 0xC4..=0xC7 => true,
+            0xD0..=0xD3 | 0xD8..=0xDF | 0xF6 | 0xF7 | 0xFE | 0xFF => true,
+            _ => false,
+        },
+        (Encoding::Legacy, 1) => !matches!(
+            op,
+            0x05..=0x09
+                | 0x0B
+                | 0x0E
+                | 0x30..=0x37
+                | 0x77
+                | 0x80..=0x8F
+                | 0xA0..=0xA2
+                | 0xA8..=0xAA
+                | 0xC8..=0xCF
+        ),
+        (Encoding::Vex { .. }, 1) => op != 0x77,
+        _ => true,
+    };
+    let modrm = if has_modrm { Some(*code.get(i)?) } else { None };
+    if let Some(modrm) = modrm {
+        i += modrm_len(code, i, modrm)?;
+    }
+    let reg = modrm.map_or(0, |m| (m >> 3) & 7);
+
+    let z = if p66 { 2 } else { 4 };
+    i += match (encoding, map) {
+        (Encoding::Legacy, 0) | (Encoding::Evex { .. }, 4) => match op {
+            0x00..=0x3F => match op & 7 {
+                4 => 1,
+                5 => z,
+                _ => 0,
+            },
+            0x68 | 0x69 | 0x81 | 0xA9 | 0xC7 => z,
+            0x6A | 0x6B | 0x70..=0x7F | 0x80 | 0x82 | 0x83 | 0xA8 | 0xB0..=0xB7 => 1,
+            0xC0 | 0xC1 | 0xC6 | 0xCD | 0xE0..=0xE7 | 0xEB => 1,
+            0xA0..=0xA3 if addr32 => 4,
+            0xA0..=0xA3 => 8,
+            0xB8..=0xBF if rex_w => 8,
+            0xB8..=0xBF => z,
+            0xC2 | 0xCA => 2,
+            0xC8 => 3,
+            0xE8 | 0xE9 => 4,
+            0xF6 if reg < 2 => 1,
+            0xF7 if reg < 2 => z,
+            _ => 0,
+        },
+        (Encoding::Legacy, 1) => match op {
+            0x0F | 0x70..=0x73 | 0xA4 | 0xAC | 0xBA | 0xC2 | 0xC4..=0xC6 => 1,
+            0x78 if p66 || rep == Some(0xF2) => 2,
+            0x80..=0x8F => 4,
+            _ => 0,
+        },
+        (Encoding::Vex { .. } | Encoding::Evex { .. }, 1) => match op {
+            0x70..=0x73 | 0xC2 | 0xC4..=0xC6 => 1,
+            _ => 0,
+        },
+        (Encoding::Legacy | Encoding::Vex { .. } | Encoding::Evex { .. }, 3)
+        | (Encoding::Xop, 8) => 1,
+        (Encoding::Evex { .. }, 7) => 1,
+        (Encoding::Xop, 0xA) => 4,
+        _ => 0,
+    };
+    if i > code.len() {
+        return None;
+    }
+
+    let is_reg = modrm.is_some_and(|m| m >> 6 == 3);
+    let feature = match encoding {
+        Encoding::Legacy if apx => Some("apxf"),
+        Encoding::Legacy => legacy_feature(map, op, p66, rep, rex_w, reg, is_reg),
+        Encoding::Vex { pp, l } => Some(vex_feature(map, op, pp, l, is_reg)),
+        Encoding::Evex { pp, ll, b, w } => {
+            // Embedded rounding, which implies 512-bit vectors.
+            let xmm_or_ymm = ll < 2 && !(b && is_reg);
+            let vl = xmm_or_ymm && !length_ignored(map, op, pp);
+            Some(evex_feature(map, op, pp, w, reg, vl))
+        }
+        Encoding::Xop => Some("xop"),
+    };
+    let width = match encoding {
+        _ if apx || is_move(map, op) => None,
+        Encoding::Legacy => legacy_width(map, op, p66, rep),
+        Encoding::Vex { pp, l } => vex_width(map, op, pp, l),
+        Encoding::Evex { pp, ll, b, .. } => match map {
+            4 => None,
+            _ if is_scalar(map, op, pp) => Some(Width::Scalar),
+            // Embedded rounding, which implies 512-bit vectors.
+            _ if b && is_reg => Some(Width::Bits512),
+            _ => Some(match ll {
+                0 => Width::Bits128,
+                1 => Width::Bits256,
+                _ => Width::Bits512,
+            }),
+        },
+        Encoding::Xop => Some(Width::Bits128),
+    };
+    Some(Insn {
+        len: i,
+        feature,
+        width,
+    })
+}
+
+/// The length of the ModRM byte at `code[i]`, and the SIB byte and displacement following it.
+fn modrm_len(code: &[u8], i: usize, modrm: u8) -> Option<usize> {
+    let (md, rm) = (modrm >> 6, modrm & 7);
+    if md == 3 {
+        return Some(1);
+    }
+    let mut len = 1;
+    if rm == 4 {
+        let sib = *code.get(i + 1)?;
+        len += 1;
+        if md == 0 && sib & 7 == 5 {
+            len += 4;
+        }
+    } else if md == 0 && rm == 5 {
+        // RIP-relative.
+        len += 4;
+    }
+    Some(match md {
+        1 => len + 1,
+        2 => len + 4,
+        _ => len,
+    })
+}
+
+/// The extension of an instruction without VEX or EVEX prefix.
+fn legacy_feature(
+    map: u8,
+    op: u8,
+    p66: bool,
+    rep: Option<u8>,
+    rex_w: bool,
+    reg: u8,
+    is_reg: bool,
+) -> Option<&'static str> {
+    // `tzcnt` is not reported: it executes as `bsf` on CPUs without BMI1, and compilers emit it
+    // for the baseline when the input is known to be non-zero.
+    Some(match (map, op) {
+        (1, 0xB8) if rep == Some(0xF3) => "popcnt",
+        (1, 0xBD) if rep == Some(0xF3) => "lzcnt",
+        (1, 0x12) if rep.is_some() => "sse3",
+        (1, 0x16) if rep == Some(0xF3) => "sse3",
+        (1, 0xF0) if rep == Some(0xF2) => "sse3",
+        (1, 0x7C | 0x7D | 0xD0) if p66 || rep == Some(0xF2) => "sse3",
+        (1, 0x78 | 0x79) if p66 || rep == Some(0xF2) => "sse4a",
+        (1, 0x2B) if rep.is_some() => "sse4a",
+        (1, 0xC7) if rex_w && reg == 1 && !is_reg => "cmpxchg16b",
+        // With an `F3` prefix, these are `senduipi` and `rdpid`.
+        (1, 0xC7) if reg == 6 && is_reg && rep.is_none() => "rdrand",
+        (1, 0xC7) if reg == 7 && is_reg && rep.is_none() => "rdseed",
+        (2, 0xF0 | 0xF1) if rep == Some(0xF2) => "sse4.2",
+        (2, 0xF0 | 0xF1) => "movbe",
+        (2, 0x00..=0x0B | 0x1C..=0x1E) => "ssse3",
+        (2, 0x37) => "sse4.2",
+        (2, 0x10 | 0x14 | 0x15 | 0x17 | 0x20..=0x25 | 0x28..=0x2B | 0x30..=0x35 | 0x38..=0x41)
+            if p66 =>
+        {
+            "sse4.1"
+        }
+        (2, 0xC8..=0xCD) | (3, 0xCC) => "sha",
+        (2, 0xCF) | (3, 0xCE | 0xCF) => "gfni",
+        (2, 0xDB..=0xDF) | (3, 0xDF) => "aes",
+        (2, 0xF6) if p66 || rep == Some(0xF3) => "adx",
+        (3, 0x0F) => "ssse3",
+        (3, 0x08..=0x0E | 0x14..=0x17 | 0x20..=0x22 | 0x40..=0x42) => "sse4.1",
+        (3, 0x60..=0x63) => "sse4.2",
+        (3, 0x44) => "pclmulqdq",
+        _ => return None,
+    })
+}
+
+/// The extension of a VEX-encoded instruction, with mandatory prefix `pp` and vector length `l`.
+fn vex_feature(map: u8, op: u8, pp: u8, l: u8, is_reg: bool) -> &'static str {
+    let ymm = l == 1;
+    match (map, pp, op) {
+        // Integer instructions on 256-bit vectors.
+        (
+            1,
+            1,
+            0x60..=0x6D
+            | 0x70..=0x76
+            | 0xD1..=0xD5
+            | 0xD7..=0xDF
+            | 0xE0..=0xE5
+            | 0xE8..=0xEF
+            | 0xF1..=0xF6
+            | 0xF8..=0xFE,
+        ) if ymm => "avx2",
+        (1, 2 | 3, 0x70) if ymm => "avx2",
+        // AVX-512 mask register instructions.
+        (1, _, 0x41..=0x4B | 0x90..=0x93 | 0x98 | 0x99) | (3, _, 0x30..=0x33) => "avx512f",
+        // Instructions on general purpose registers.
+        (2, 0, 0xF2 | 0xF3 | 0xF7) => "bmi1",
+        (2, _, 0xF5..=0xF7) | (3, 3, 0xF0) => "bmi2",
+        _ if let Some(amx) = amx_feature(map, op, pp) => amx,
+        (2, 1, 0x13) | (3, 1, 0x1D) => "f16c",
+        (2, 1, 0x96..=0x9F | 0xA6..=0xAF | 0xB6..=0xBF) => "fma",
+        (
+            2,
+            1,
+            0x16 | 0x36 | 0x45..=0x47 | 0x58..=0x5A | 0x78 | 0x79 | 0x8C | 0x8E | 0x90..=0x93,
+        ) => "avx2",
+        // Broadcasts from a register.
+        (2, 1, 0x18 | 0x19) if is_reg => "avx2",
+        (
+            2,
+            1,
+            0x00..=0x0B | 0x1C..=0x1E | 0x20..=0x25 | 0x28..=0x2B | 0x30..=0x35 | 0x37..=0x40,
+        ) if ymm => "avx2",
+        (2, 1, 0x50..=0x53) => "avxvnni",
+        (2, 0 | 2 | 3, 0x50 | 0x51) => "avxvnniint8",
+        (2, 0..=2, 0xD2 | 0xD3) => "avxvnniint16",
+        (2, 1, 0xB4 | 0xB5) => "avxifma",
+        (2, _, 0xB0) | (2, 1 | 2, 0xB1) | (2, 2, 0x72) => "avxneconvert",
+        (2, 3, 0xCB..=0xCD) => "sha512",
+        (2, 0 | 1, 0xDA) | (3, 1, 0xDE) => "sm3",
+        (2, 2 | 3, 0xDA) => "sm4",
+        (2, 1, 0xDB..=0xDF) | (3, 1, 0xDF) if ymm => "vaes",
+        (2, 1, 0xDB..=0xDF) | (3, 1, 0xDF) => "aes",
+        (3, 1, 0x00..=0x02 | 0x38 | 0x39 | 0x46) => "avx2",
+        (3, 1, 0x0E | 0x0F | 0x42 | 0x4C) if ymm => "avx2",
+        (3, 1, 0x44) if ymm => "vpclmulqdq",
+        (3, 1, 0x44) => "pclmulqdq",
+        _ => "avx",
+    }
+}
+
+/// The extension of an EVEX-encoded instruction, with mandatory prefix `pp`, the `W` bit `w`, and
+/// the `reg` field of the ModRM byte.
+///
+/// Instructions of AVX512F on 128-bit or 256-bit vectors (`vl`) are reported as `avx512vl`.
+/// Those of the other extensions are reported by their extension, although they also require it.
+fn evex_feature(map: u8, op: u8, pp: u8, w: bool, reg: u8, vl: bool) -> &'static str {
+    match (map, pp, op) {
+        (4, _, _) => "apxf",
+        (5 | 6, _, _) => "avx512fp16",
+        // `vrndscaleph`, `vgetmantph`, `vreduceph`, `vfpclassph`, `vcmpph`, and their scalar forms.
+        (3, 0, 0x08 | 0x0A | 0x26 | 0x27 | 0x56 | 0x57 | 0x66 | 0x67 | 0xC2) => "avx512fp16",
+        // Byte and word integer instructions.
+        (
+            1,
+            1,
+            0x60 | 0x61 | 0x63..=0x65 | 0x67..=0x69 | 0x6B | 0x71 | 0x74 | 0x75 | 0xC4 | 0xC5,
+        )
+        | (1, 1, 0xD1 | 0xD5 | 0xD8..=0xDA | 0xDC..=0xDE | 0xE0 | 0xE1 | 0xE3..=0xE5)
+        | (1, 1, 0xE8..=0xEA | 0xEC..=0xEE | 0xF1 | 0xF5 | 0xF6 | 0xF8 | 0xF9 | 0xFC | 0xFD)
+        | (1, 2 | 3, 0x70)
+        | (1, 3, 0x6F | 0x7F)
+        | (2, 1, 0x00 | 0x04 | 0x0B | 0x1C | 0x1D | 0x20 | 0x2B | 0x30 | 0x38 | 0x3A | 0x3C)
+        | (2, 1, 0x10..=0x12 | 0x3E | 0x66 | 0x78..=0x7B)
+        | (2, 1 | 2, 0x26)
+        | (2, 2, 0x10 | 0x20 | 0x28 | 0x29 | 0x30)
+        | (3, 1, 0x0F | 0x14 | 0x15 | 0x20 | 0x3E | 0x3F | 0x42) => "avx512bw",
+        // `vpslldq` and `vpsrldq`.
+        (1, 1, 0x73) if reg == 3 || reg == 7 => "avx512bw",
+        (2, 1, 0x75 | 0x7D | 0x8D) if w => "avx512bw",
+        (2, 1, 0x75 | 0x7D | 0x8D) | (2, 1, 0x83) => "avx512vbmi",
+        (2, 1, 0x62 | 0x63 | 0x70..=0x73) | (3, 1, 0x70..=0x73) => "avx512vbmi2",
+        (2, 2 | 3, 0x72) | (2, 2, 0x52) => "avx512bf16",
+        (2, 1, 0x50..=0x53) => "avx512vnni",
+        (2, 1, 0xB4 | 0xB5) => "avx512ifma",
+        (2, 1, 0x54 | 0x8F) => "avx512bitalg",
+        (2, 1, 0x55) => "avx512vpopcntdq",
+        (2, 1, 0x44 | 0xC4) | (2, 2, 0x2A | 0x3A) => "avx512cd",
+        (2, 3, 0x68) => "avx512vp2intersect",
+        // Floating-point logic, conversions from and to 64-bit integers, and `vrangeps` and friends.
+        (1, 0 | 1, 0x54..=0x57)
+        | (1, 1, 0x78..=0x7B)
+        | (2, 2, 0x38 | 0x39)
+        | (3, 1, 0x16 | 0x22 | 0x50 | 0x51 | 0x56 | 0x57 | 0x66 | 0x67) => "avx512dq",
+        (1, 0, 0x5B) | (1, 2, 0xE6) | (1, 2 | 3, 0x7A) | (2, 1, 0x40) if w => "avx512dq",
+        // Broadcasts, inserts, and extracts of 64-bit elements in 128-bit blocks, or of 256-bit
+        // blocks of 32-bit elements.
+        (2, 1, 0x1A | 0x5A) | (3, 1, 0x18 | 0x19 | 0x38 | 0x39) if w => "avx512dq",
+        (2, 1, 0x19 | 0x1B | 0x59 | 0x5B) | (3, 1, 0x1A | 0x1B | 0x3A | 0x3B) if !w => "avx512dq",
+        (2, 1, 0xCF) | (3, 1, 0xCE | 0xCF) => "gfni",
+        (2, 1, 0xDC..=0xDF) => "vaes",
+        (3, 1, 0x44) => "vpclmulqdq",
+        _ if vl => "avx512vl",
+        _ => "avx512f",
+    }
+}
+
+/// Whether an EVEX-encoded instruction ignores the vector length, because it operates on a single
+/// element, or moves one between a vector and another register.
+fn length_ignored(map: u8, op: u8, pp: u8) -> bool {
+    is_scalar(map, op, pp)
+        || matches!(
+            (map, pp, op),
+            // `vmovss`, `vmovsd`, `vmovd`, `vmovq`, `vpinsrw`, and `vpextrw`.
+            (1, 2 | 3, 0x10 | 0x11)
+                | (1, 1, 0x6E | 0x7E | 0xD6 | 0xC4 | 0xC5)
+                | (1, 2, 0x7E)
+                // Conversions between scalars and unsigned integers.
+                | (1, 2 | 3, 0x78 | 0x79 | 0x7B)
+                // `vscalefss`, `vgetexpss`, `vrcp14ss`, and `vrsqrt14ss`.
+                | (2, 1, 0x2D | 0x43 | 0x4D | 0x4F)
+                // Inserts and extracts of elements, and `vgetmantss`, `vrangess`, `vfixupimmss`,
+                // `vreducess`, and `vfpclassss`.
+                | (3, 1, 0x14..=0x17 | 0x20..=0x22 | 0x27 | 0x51 | 0x55 | 0x57 | 0x67)
+        )
+}
+
+/// The AMX extension of a VEX-encoded instruction on tile registers, if it is one.
+fn amx_feature(map: u8, op: u8, pp: u8) -> Option<&'static str> {
+    Some(match (map, pp, op) {
+        // `ldtilecfg`, `sttilecfg`, `tilerelease`, `tilezero`, `tileloadd`, and `tilestored`.
+        (2, _, 0x49 | 0x4B) => "amx-tile",
+        (2, 2, 0x5C) => "amx-bf16",
+        (2, 3, 0x5C) => "amx-fp16",
+        (2, _, 0x5E) => "amx-int8",
+        _ => return None,
+    })
+}
+
+/// Whether the instruction only moves data: loads, stores, and register moves.
+///
+/// These are not counted as vector code, since they are also used to copy memory in scalar code.
+fn is_move(map: u8, op: u8) -> bool {
+    match map {
+        1 => matches!(
+            op,
+            0x10..=0x17 | 0x28 | 0x29 | 0x2B | 0x6E | 0x6F | 0x77 | 0x7E | 0x7F | 0xD6 | 0xE7
+        ),
+        2 => matches!(op, 0x18..=0x1A | 0x2A | 0x58..=0x5A | 0x78 | 0x79),
+        _ => false,
+    }
+}
+
+/// Whether the instruction operates on a single element, e.g. `addss`, with mandatory prefix `pp`
+/// (1 for `66`, 2 for `F3`, 3 for `F2`).
+fn is_scalar(map: u8, op: u8, pp: u8) -> bool {
+    match map {
+        // Including the FP16 maps of AVX512-FP16.
+        1 | 5 => {
+            matches!(op, 0x2E | 0x2F)
+                || pp >= 2 && matches!(op, 0x2A | 0x2C | 0x2D | 0x51..=0x5A | 0x5C..=0x5F | 0xC2)
+        }
+        // `vfmadd231ss` and friends.
+        2 => pp == 1 && op & 1 == 1 && matches!(op, 0x99..=0x9F | 0xA9..=0xAF | 0xB9..=0xBF),
+        // `roundss` and `roundsd`.
+        3 => matches!(op, 0x0A | 0x0B),
+        _ => false,
+    }
+}
+
+/// The width of an SSE or MMX instruction.
+fn legacy_width(map: u8, op: u8, p66: bool, rep: Option<u8>) -> Option<Width> {
+    let pp = match (p66, rep) {
+        (_, Some(0xF3)) => 2,
+        (_, Some(_)) => 3,
+        (true, None) => 1,
+        (false, None) => 0,
+    };
+    let simd = match map {
+        1 => {
+            matches!(op, 0x10..=0x17 | 0x28..=0x2F | 0x50..=0x7F | 0xC2 | 0xC4..=0xC6 | 0xD0..=0xFE)
+        }
+        2 => !matches!(op, 0xF0..=0xFF),
+        3 => op != 0xF0,
+        _ => false,
+    };
+    if !simd {
+        None
+    } else if is_scalar(map, op, pp) {
+        Some(Width::Scalar)
+    } else if pp == 0 && is_mmx(map, op) {
+        Some(Width::Bits64)
+    } else if pp == 0 && map != 1 && !matches!((map, op), (2, 0xC8..=0xCF) | (3, 0xCC..=0xCF)) {
+        // Only SHA and GFNI use `xmm` registers without `66` prefix in these maps.
+        None
+    } else {
+        Some(Width::Bits128)
+    }
+}
+
+/// Whether the instruction operates on MMX registers when it has no mandatory prefix.
+fn is_mmx(map: u8, op: u8) -> bool {
+    match map {
+        1 => matches!(op, 0x60..=0x7F | 0xC4 | 0xC5 | 0xD0..=0xFE),
+        2 => matches!(op, 0x00..=0x1E),
+        3 => op == 0x0F,
+        _ => false,
+    }
+}
+
+/// The width of a VEX-encoded instruction, with mandatory prefix `pp` and vector length `l`.
+fn vex_width(map: u8, op: u8, pp: u8, l: u8) -> Option<Width> {
+    match (map, op) {
+        // Instructions on general purpose, mask, and tile registers.
+        (2, 0xF0..=0xF7) | (3, 0xF0) | (3, 0x30..=0x33) => None,
+        (1, 0x41..=0x4B | 0x90..=0x93 | 0x98 | 0x99) => None,
+        _ if amx_feature(map, op, pp).is_some() => None,
+        _ if is_scalar(map, op, pp) => Some(Width::Scalar),
+        _ if l == 1 => Some(Width::Bits256),
+        _ => Some(Width::Bits128),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode `code` as a single instruction, as `(feature, width)`.
+    fn decode_one(code: &[u8]) -> (Option<&'static str>, Option<Width>) {
+        let insn = decode(code).unwrap();
+        assert_eq!(insn.len, code.len(), "{code:02x?}");
+        (insn.feature, insn.width)
+    }
+
+    #[test]
+    fn legacy() {
+        // `nop`.
+        assert_eq!(decode_one(&[0x90]), (None, None));
+        // `movabs rax, 0x0807060504030201`.
+        assert_eq!(
+            decode_one(&[0x48, 0xB8, 1, 2, 3, 4, 5, 6, 7, 8]),
+            (None, None)
+        );
+        // `mov rax, [rip + 0x10]`.
+        assert_eq!(decode_one(&[0x48, 0x8B, 0x05, 0x10, 0, 0, 0]), (None, None));
+        // `popcnt eax, eax`.
+        assert_eq!(
+            decode_one(&[0xF3, 0x0F, 0xB8, 0xC0]),
+            (Some("popcnt"), None)
+        );
+        // `addps xmm0, xmm1`, `addss xmm0, xmm1`, and `paddd mm0, mm1`.
+        assert_eq!(
+            decode_one(&[0x0F, 0x58, 0xC1]),
+            (None, Some(Width::Bits128))
+        );
+        assert_eq!(
+            decode_one(&[0xF3, 0x0F, 0x58, 0xC1]),
+            (None, Some(Width::Scalar))
+        );
+        assert_eq!(decode_one(&[0x0F, 0xFE, 0xC1]), (None, Some(Width::Bits64)));
+        // `pshufb xmm0, xmm1`.
+        assert_eq!(
+            decode_one(&[0x66, 0x0F, 0x38, 0x00, 0xC1]),
+            (Some("ssse3"), Some(Width::Bits128))
+        );
+    }
+
+    #[test]
+    fn vex() {
+        // `vpaddd ymm0, ymm0, ymm1` and `vpaddd xmm0, xmm0, xmm1`.
+        assert_eq!(
+            decode_one(&[0xC5, 0xFD, 0xFE, 0xC1]),
+            (Some("avx2"), Some(Width::Bits256))
+        );
+        assert_eq!(
+            decode_one(&[0xC5, 0xF9, 0xFE, 0xC1]),
+            (Some("avx"), Some(Width::Bits128))
+        );
+        // `vaddss xmm0, xmm0, xmm1`.
+        assert_eq!(
+            decode_one(&[0xC5, 0xFA, 0x58, 0xC1]),
+            (Some("avx"), Some(Width::Scalar))
+        );
+        // `vpblendvb ymm0, ymm0, ymm1, ymm2` and `vpblendvb xmm0, xmm0, xmm1, xmm2`.
+        assert_eq!(
+            decode_one(&[0xC4, 0xE3, 0x7D, 0x4C, 0xC1, 0x20]),
+            (Some("avx2"), Some(Width::Bits256))
+        );
+        assert_eq!(
+            decode_one(&[0xC4, 0xE3, 0x79, 0x4C, 0xC1, 0x20]),
+            (Some("avx"), Some(Width::Bits128))
+        );
+        // `vfmadd231ps ymm0, ymm1, ymm2`.
+        assert_eq!(
+            decode_one(&[0xC4, 0xE2, 0x75, 0xB8, 0xC2]),
+            (Some("fma"), Some(Width::Bits256))
+        );
+        // `vzeroupper`.
+        assert_eq!(decode_one(&[0xC5, 0xF8, 0x77]), (Some("avx"), None));
+    }
+
+    #[test]
+    fn general_purpose() {
+        // `shlx rax, rax, rax` and `andn eax, eax, eax`.
+        assert_eq!(
+            decode_one(&[0xC4, 0xE2, 0xF9, 0xF7, 0xC0]),
+            (Some("bmi2"), None)
+        );
+        assert_eq!(
+            decode_one(&[0xC4, 0xE2, 0x78, 0xF2, 0xC0]),
+            (Some("bmi1"), None)
+        );
+        // `kmovw k1, eax`.
+        assert_eq!(
+            decode_one(&[0xC5, 0xF8, 0x92, 0xC8]),
+            (Some("avx512f"), None)
+        );
+    }
+
+    #[test]
+    fn amx() {
+        // `tilerelease`.
+        assert_eq!(
+            decode_one(&[0xC4, 0xE2, 0x78, 0x49, 0xC0]),
+            (Some("amx-tile"), None)
+        );
+        // `tdpbf16ps tmm0, tmm1, tmm2`, `tdpfp16ps tmm0, tmm1, tmm2`, and `tdpbssd tmm0, tmm1, tmm2`.
+        assert_eq!(
+            decode_one(&[0xC4, 0xE2, 0x6A, 0x5C, 0xC1]),
+            (Some("amx-bf16"), None)
+        );
+        assert_eq!(
+            decode_one(&[0xC4, 0xE2, 0x6B, 0x5C, 0xC1]),
+            (Some("amx-fp16"), None)
+        );
+        assert_eq!(
+            decode_one(&[0xC4, 0xE2, 0x6B, 0x5E, 0xC1]),
+            (Some("amx-int8"), None)
+        );
+    }
+
+    #[test]
+    fn evex() {
+        // `vpaddd zmm0, zmm0, zmm1` and `vpaddd xmm16, xmm16, xmm1`.
+        assert_eq!(
+            decode_one(&[0x62, 0xF1, 0x7D, 0x48, 0xFE, 0xC1]),
+            (Some("avx512f"), Some(Width::Bits512))
+        );
+        assert_eq!(
+            decode_one(&[0x62, 0xE1, 0x7D, 0x00, 0xFE, 0xC1]),
+            (Some("avx512vl"), Some(Width::Bits128))
+        );
+        // `vaddps zmm0, zmm0, zmm1, {rn-sae}`, where the vector length encodes the rounding mode.
+        assert_eq!(
+            decode_one(&[0x62, 0xF1, 0x7C, 0x18, 0x58, 0xC1]),
+            (Some("avx512f"), Some(Width::Bits512))
+        );
+        // `vaddss xmm16, xmm16, xmm1` and `vmovd xmm16, eax`.
+        assert_eq!(
+            decode_one(&[0x62, 0xE1, 0x7E, 0x00, 0x58, 0xC1]),
+            (Some("avx512f"), Some(Width::Scalar))
+        );
+        assert_eq!(
+            decode_one(&[0x62, 0xE1, 0x7D, 0x08, 0x6E, 0xC0]),
+            (Some("avx512f"), None)
+        );
+        // `vaddph zmm0, zmm0, zmm1`.
+        assert_eq!(
+            decode_one(&[0x62, 0xF5, 0x7C, 0x48, 0x58, 0xC1]),
+            (Some("avx512fp16"), Some(Width::Bits512))
+        );
+    }
+
+    #[test]
+    fn evex_extensions() {
+        for (code, feature) in [
+            // `vpaddb zmm0, zmm0, zmm1` and `vpaddb xmm16, xmm16, xmm1`.
+            (&[0x62, 0xF1, 0x7D, 0x48, 0xFC, 0xC1][..], "avx512bw"),
+            (&[0x62, 0xE1, 0x7D, 0x00, 0xFC, 0xC1], "avx512bw"),
+            // `vpsrldq zmm0, zmm1, 4` and `vpsrld zmm0, zmm1, 4`.
+            (&[0x62, 0xF1, 0x7D, 0x48, 0x73, 0xD9, 0x04], "avx512bw"),
+            (&[0x62, 0xF1, 0x7D, 0x48, 0x72, 0xD1, 0x04], "avx512f"),
+            // `vpermb zmm0, zmm0, zmm1` and `vpermw zmm0, zmm0, zmm1`.
+            (&[0x62, 0xF2, 0x7D, 0x48, 0x8D, 0xC1], "avx512vbmi"),
+            (&[0x62, 0xF2, 0xFD, 0x48, 0x8D, 0xC1], "avx512bw"),
+            // `vpconflictd zmm0, zmm1`.
+            (&[0x62, 0xF2, 0x7D, 0x48, 0xC4, 0xC1], "avx512cd"),
+            // `vandps zmm0, zmm0, zmm1`.
+            (&[0x62, 0xF1, 0x7C, 0x48, 0x54, 0xC1], "avx512dq"),
+            // `vbroadcastf32x2 zmm0, xmm1` and `vbroadcastsd zmm0, xmm1`.
+            (&[0x62, 0xF2, 0x7D, 0x48, 0x19, 0xC1], "avx512dq"),
+            (&[0x62, 0xF2, 0xFD, 0x48, 0x19, 0xC1], "avx512f"),
+            // `vpdpbusd zmm0, zmm0, zmm1`.
+            (&[0x62, 0xF2, 0x7D, 0x48, 0x50, 0xC1], "avx512vnni"),
+        ] {
+            assert_eq!(decode_one(code).0, Some(feature), "{code:02x?}");
+        }
+    }
+
+    #[test]
+    fn vex_extensions() {
+        for (code, feature) in [
+            // `vpdpbssd xmm0, xmm1, xmm2` and `vpdpbuud xmm0, xmm1, xmm2`.
+            (&[0xC4, 0xE2, 0x73, 0x50, 0xC2][..], "avxvnniint8"),
+            (&[0xC4, 0xE2, 0x70, 0x50, 0xC2], "avxvnniint8"),
+            // `vpdpbusd xmm0, xmm1, xmm2`.
+            (&[0xC4, 0xE2, 0x71, 0x50, 0xC2], "avxvnni"),
+            // `vpdpwsud xmm0, xmm1, xmm2` and `vpdpwuuds ymm0, ymm1, ymm2`.
+            (&[0xC4, 0xE2, 0x72, 0xD2, 0xC2], "avxvnniint16"),
+            (&[0xC4, 0xE2, 0x74, 0xD3, 0xC2], "avxvnniint16"),
+            // `{vex} vpmadd52luq xmm0, xmm1, xmm2` and `{vex} vpmadd52huq ymm0, ymm1, ymm2`.
+            (&[0xC4, 0xE2, 0xF1, 0xB4, 0xC2], "avxifma"),
+            (&[0xC4, 0xE2, 0xF5, 0xB5, 0xC2], "avxifma"),
+            // `vbcstnebf162ps xmm0, [rax]`, `vcvtneoph2ps ymm0, [rax]`, and
+            // `{vex} vcvtneps2bf16 xmm0, xmm1`.
+            (&[0xC4, 0xE2, 0x7A, 0xB1, 0x00], "avxneconvert"),
+            (&[0xC4, 0xE2, 0x7C, 0xB0, 0x00], "avxneconvert"),
+            (&[0xC4, 0xE2, 0x7A, 0x72, 0xC1], "avxneconvert"),
+            // `vsha512msg1 ymm0, xmm1` and `vsha512rnds2 ymm0, ymm1, xmm2`.
+            (&[0xC4, 0xE2, 0x7F, 0xCC, 0xC1], "sha512"),
+            (&[0xC4, 0xE2, 0x77, 0xCB, 0xC2], "sha512"),
+            // `vsm3msg1 xmm0, xmm1, xmm2` and `vsm3rnds2 xmm0, xmm1, xmm2, 1`.
+            (&[0xC4, 0xE2, 0x70, 0xDA, 0xC2], "sm3"),
+            (&[0xC4, 0xE3, 0x71, 0xDE, 0xC2, 0x01], "sm3"),
+            // `vsm4key4 xmm0, xmm1, xmm2` and `vsm4rnds4 ymm0, ymm1, ymm2`.
+            (&[0xC4, 0xE2, 0x72, 0xDA, 0xC2], "sm4"),
+            (&[0xC4, 0xE2, 0x77, 0xDA, 0xC2], "sm4"),
+        ] {
+            assert_eq!(decode_one(code).0, Some(feature), "{code:02x?}");
+        }
+    }
+
+    #[test]
+    fn random() {
+        // `rdrand eax`, `rdrand rax`, `rdseed eax`, and `rdseed ax`.
+        assert_eq!(decode_one(&[0x0F, 0xC7, 0xF0]), (Some("rdrand"), None));
+        assert_eq!(
+            decode_one(&[0x48, 0x0F, 0xC7, 0xF0]),
+            (Some("rdrand"), None)
+        );
+        assert_eq!(decode_one(&[0x0F, 0xC7, 0xF8]), (Some("rdseed"), None));
+        assert_eq!(
+            decode_one(&[0x66, 0x0F, 0xC7, 0xF8]),
+            (Some("rdseed"), None)
+        );
+        // `rdpid rax`.
+        assert_eq!(decode_one(&[0xF3, 0x0F, 0xC7, 0xF8]), (None, None));
+    }
+
+    #[test]
+    fn truncated() {
+        for code in [
+            &[][..],
+            &[0x66],
+            &[0xC5, 0xFD, 0xFE],
+            &[0x48, 0xB8, 1, 2, 3, 4],
+            &[0x62, 0xF1, 0x7D],
+            &[0x48, 0x8B, 0x05, 0x10],
+        ] {
+            assert!(decode(code).is_none(), "{code:02x?}");
+        }
+    }
+}
@@ -0,0 +1,57 @@
+/*
+ * Calls every function declared in include/ensure_simd.h, linked against the `staticlib`.
+ * Built and run by tests/capi_header.rs. Exits with 0 when all results are consistent.
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "ensure_simd.h"
+
+static int failures = 0;
+
+static void expect_name(int level, const char *expected) {
+    const char *name = ensure_simd_level_name(level);
+    if (expected == NULL ? name != NULL : name == NULL || strcmp(name, expected) != 0) {
+        fprintf(stderr, "ensure_simd_level_name(%d) = %s, expected %s\n", level,
+                name ? name : "NULL", expected ? expected : "NULL");
+        failures++;
+    }
+}
+
+static void expect_level(const char *function, int level) {
+    if (ensure_simd_level_name(level) == NULL) {
+        fprintf(stderr, "%s() = %d, which is not a level\n", function, level);
+        failures++;
+    }
+}
+
+int main(void) {
+    expect_name(ENSURE_SIMD_SCALAR, "scalar");
+    expect_name(ENSURE_SIMD_X86_64_V1, "x86-64");
+    expect_name(ENSURE_SIMD_X86_64_V2, "x86-64-v2");
+    expect_name(ENSURE_SIMD_X86_64_V3, "x86-64-v3");
+    expect_name(ENSURE_SIMD_X86_64_V4, "x86-64-v4");
+    expect_name(ENSURE_SIMD_NEON, "neon");
+    expect_name(ENSURE_SIMD_SVE, "sve");
+    expect_name(ENSURE_SIMD_SVE2, "sve2");
+    expect_name(-1, NULL);
+    expect_name(ENSURE_SIMD_SVE2 + 1, NULL);
+
+    expect_level("ensure_simd_level", ensure_simd_level());
+    expect_level("ensure_simd_compiled_level", ensure_simd_compiled_level());
+    expect_level("ensure_simd_required_level", ensure_simd_required_level());
+
+    char buf[4096];
+    int result = ensure_simd_check(buf, sizeof buf);
+    if (result != 0 && (result != 1 || strlen(buf) == 0)) {
+        fprintf(stderr, "ensure_simd_check() = %d, with message `%s`\n", result, buf);
+        failures++;
+    }
+    if (ensure_simd_check(NULL, 0) != result) {
+        fprintf(stderr, "ensure_simd_check(NULL, 0) differs from ensure_simd_check(buf, len)\n");
+        failures++;
+    }
+
+    return failures != 0;
+}
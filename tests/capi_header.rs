@@ -0,0 +1,62 @@
+//! `include/ensure_simd.h` must match the functions exported by `src/capi.rs`.
+//!
+//! Builds the crate as a `staticlib`, and compiles and links `tests/capi_header.c` against it
+//! using the system C compiler `cc`.
+
+#![cfg(all(feature = "capi", target_os = "linux"))]
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn header_matches_exported_symbols() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // A separate target directory, since the one running this test is locked.
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("capi");
+
+    let status = Command::new(env!("CARGO"))
+        .args([
+            "rustc",
+            "--lib",
+            "--crate-type",
+            "staticlib",
+            "--features",
+            "capi",
+        ])
+        .arg("--manifest-path")
+        .arg(root.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success(), "building the staticlib failed");
+
+    let exe = target.join("capi_header");
+    let output = match Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-I"])
+        .arg(root.join("include"))
+        .arg(root.join("tests/capi_header.c"))
+        .arg(target.join("debug/libensure_simd.a"))
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&exe)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("Skipped: could not run cc: {err}.");
+            return;
+        }
+    };
+    assert!(
+        output.status.success(),
+        "compiling the header failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = Command::new(&exe).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
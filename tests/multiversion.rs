@@ -0,0 +1,75 @@
+//! `#[ensure_simd::multiversion]` on generic functions and functions with an early `return`,
+//! and the level its dispatch picks on simulated CPUs.
+
+#![cfg(feature = "macros")]
+
+use core::ops::Add;
+
+use ensure_simd::multiversion;
+
+#[multiversion(v2, v3, v4)]
+fn sum<T: Copy + Add<Output = T>>(xs: &[T], zero: T) -> T {
+    xs.iter().fold(zero, |acc, &x| acc + x)
+}
+
+fn sum_scalar<T: Copy + Add<Output = T>>(xs: &[T], zero: T) -> T {
+    xs.iter().fold(zero, |acc, &x| acc + x)
+}
+
+#[multiversion(v2, v3, v4)]
+fn position(xs: &[u32], needle: u32) -> Option<usize> {
+    for (i, &x) in xs.iter().enumerate() {
+        if x == needle {
+            return Some(i);
+        }
+    }
+    None
+}
+
+fn position_scalar(xs: &[u32], needle: u32) -> Option<usize> {
+    xs.iter().position(|&x| x == needle)
+}
+
+#[test]
+fn generic() {
+    let ints: Vec<u64> = (0..1000).map(|i| i * 7 % 13).collect();
+    assert_eq!(sum(&ints, 0), sum_scalar(&ints, 0));
+    let floats: Vec<f32> = (0..1000).map(|i| i as f32 / 3.0).collect();
+    assert_eq!(sum(&floats, 0.0), sum_scalar(&floats, 0.0));
+    assert_eq!(sum::<u8>(&[], 0), 0);
+}
+
+#[test]
+fn early_return() {
+    let xs: Vec<u32> = (0..1000).map(|i| i * 3).collect();
+    for needle in [0, 3, 1500, 2997, 1, 3000] {
+        assert_eq!(
+            position(&xs, needle),
+            position_scalar(&xs, needle),
+            "{needle}"
+        );
+    }
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn dispatch_level() {
+    use ensure_simd::__private::Dispatch;
+    use ensure_simd::{FeatureDetector, FeatureSet, HostCpu, MockCpu, SimdLevel, with_detector};
+
+    let host = SimdLevel::best_for(HostCpu.detect());
+    let all = MockCpu {
+        name: "all",
+        features: FeatureSet::ALL,
+    };
+    for (cpu, level) in [
+        (MockCpu::NEHALEM, SimdLevel::X86_64V2),
+        (MockCpu::HASWELL, SimdLevel::X86_64V3),
+        (MockCpu::ZEN1, SimdLevel::X86_64V3),
+        (all, SimdLevel::X86_64V4),
+    ] {
+        let dispatched = with_detector(&cpu, || Dispatch::new().level());
+        // Never a clone the actual CPU does not support.
+        assert_eq!(dispatched, level.min(host), "{}", cpu.name);
+    }
+}
@@ -0,0 +1,67 @@
+//! The check must itself run on CPUs that lack the target features it checks for.
+//!
+//! Build with e.g. `-C target-cpu=x86-64-v3` (the default in `.cargo/config.toml`).
+//! When `qemu-x86_64` is installed, `examples/check.rs` is run on an emulated Nehalem CPU,
+//! which only supports `x86-64-v2`.
+
+#![cfg(all(target_arch = "x86_64", target_os = "linux", feature = "std"))]
+
+use ensure_simd::{SimdLevel, scan};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Functions that must run on CPUs without the enabled target features.
+const FAST_PATH: &[&str] = &[
+    "ensure_simd::features::compiled_supported",
+    "ensure_simd::features::FeatureSet::detected",
+    "ensure_simd::policy::handle_unsupported",
+    "ensure_simd::load_check::record",
+    "ensure_simd::capi::write_message",
+    "<ensure_simd::error::SimdError as core::fmt::Display>::fmt",
+];
+
+/// The `check` example, which cargo builds next to the tests.
+fn example() -> PathBuf {
+    let deps = std::env::current_exe().unwrap();
+    let dir = deps.parent().unwrap().parent().unwrap();
+    let path = dir.join("examples").join("check");
+    assert!(path.exists(), "{} is not built", path.display());
+    path
+}
+
+#[test]
+fn fast_path_only_uses_baseline_instructions() {
+    let report = scan(example()).unwrap();
+    for e in report.beyond(SimdLevel::X86_64V2) {
+        for f in FAST_PATH {
+            assert!(
+                !e.functions.iter().any(|name| name == f),
+                "{f} uses {}",
+                e.feature
+            );
+        }
+    }
+}
+
+#[test]
+fn nehalem() {
+    if !cfg!(target_feature = "avx2") {
+        eprintln!("Skipped: built without AVX2, so Nehalem supports all enabled features.");
+        return;
+    }
+    let output = match Command::new("qemu-x86_64")
+        .args(["-cpu", "Nehalem"])
+        .arg(example())
+        .env_remove("ENSURE_SIMD_SKIP")
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("Skipped: could not run qemu-x86_64: {err}.");
+            return;
+        }
+    };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(stderr.contains("requires SIMD instructions"), "{stderr}");
+}